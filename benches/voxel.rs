@@ -0,0 +1,81 @@
+#![feature(test)]
+
+extern crate test;
+
+use rts::voxel::VoxelModel;
+use test::{black_box, Bencher};
+
+// -----------------------------------------------------------------------------
+
+macro_rules! scan {
+    ($name:literal) => {
+        &include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/submodules/voxel-model/vox/scan/",
+            $name,
+            ".vox"
+        ))[..]
+    };
+}
+
+fn load(data: &[u8]) -> Vec<VoxelModel> {
+    VoxelModel::from_vox(data).unwrap()
+}
+
+fn iter(b: &mut Bencher, models: &[VoxelModel]) {
+    b.iter(|| {
+        models
+            .iter()
+            .map(|m| m.iter().filter(|&(_, v)| v != 0).count())
+            .sum::<usize>()
+    });
+}
+
+fn get(b: &mut Bencher, models: &[VoxelModel]) {
+    b.iter(|| {
+        let mut nb_voxels = 0;
+        for model in models {
+            for x in 0..128 {
+                for y in 0..128 {
+                    for z in 0..128 {
+                        let v = model.get(&(x, y, z).into()).unwrap_or(0);
+                        nb_voxels += (v != 0) as usize;
+                    }
+                }
+            }
+        }
+        black_box(nb_voxels)
+    });
+}
+
+// -----------------------------------------------------------------------------
+
+#[bench]
+fn dragon_load(b: &mut Bencher) {
+    b.iter(|| load(scan!("dragon")));
+}
+
+#[bench]
+fn dragon_iter(b: &mut Bencher) {
+    iter(b, &load(scan!("dragon")));
+}
+
+#[bench]
+fn dragon_get(b: &mut Bencher) {
+    get(b, &load(scan!("dragon")));
+}
+
+#[bench]
+fn teapot_load(b: &mut Bencher) {
+    b.iter(|| load(scan!("teapot")));
+}
+
+#[bench]
+fn teapot_iter(b: &mut Bencher) {
+    iter(b, &load(scan!("teapot")));
+}
+
+#[bench]
+fn teapot_get(b: &mut Bencher) {
+    get(b, &load(scan!("teapot")));
+}
@@ -0,0 +1,35 @@
+//! Prints the memory footprint of every scan, once.
+//!
+//! `cargo run --release --example footprint`
+
+use rts::voxel::{VoxelModel, VoxelModelStats};
+
+// -----------------------------------------------------------------------------
+
+macro_rules! scan {
+    ($name:literal) => {
+        (
+            $name,
+            &include_bytes!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/submodules/voxel-model/vox/scan/",
+                $name,
+                ".vox"
+            ))[..],
+        )
+    };
+}
+
+fn main() {
+    for (name, data) in &[scan!("dragon"), scan!("teapot")] {
+        let models = VoxelModel::from_vox(data).unwrap();
+        let stats: VoxelModelStats = models.iter().map(VoxelModel::stats).sum();
+        println!(
+            "{}: {} KiB ({} chunks, {} voxels)",
+            name,
+            stats.nb_bytes / 1024,
+            stats.nb_chunks,
+            stats.nb_voxels,
+        );
+    }
+}
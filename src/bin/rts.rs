@@ -17,11 +17,17 @@ fn main() {
 
     let mut world = World::new();
 
+    // Sessions can be recorded to, and replayed from, a file for bug reports.
+    let record_to = std::env::var("RTS_RECORD").ok();
+    let replay_from = std::env::var("RTS_REPLAY").ok();
+
     let mut dispatcher = {
         // TODO(cmc): macro this?
         use std::any::type_name_of_val as sys_id;
-        let mouse = SysMouse::default();
-        let mouse_id = sys_id(&mouse);
+        let inputs = SysInputs::default();
+        let inputs_id = sys_id(&inputs);
+        let recorder = SysRecorder::default();
+        let recorder_id = sys_id(&recorder);
         let cam = SysCamera::default();
         let cam_id = sys_id(&cam);
         let selector = SysSelector::default();
@@ -30,43 +36,54 @@ fn main() {
         let bounding_tree_id = sys_id(&bounding_tree);
         let renderer = SysRenderer::new(rl_thread.clone());
 
-        DispatcherBuilder::new()
-            .with(mouse, mouse_id, &[])
-            .with(cam, cam_id, &[])
+        let mut builder = DispatcherBuilder::new();
+        // The replayer takes the place of the live inputs, id included.
+        if replay_from.is_some() {
+            builder.add(SysReplayer::default(), inputs_id, &[]);
+        } else {
+            builder.add(inputs, inputs_id, &[]);
+        }
+        if record_to.is_some() {
+            builder.add(recorder, recorder_id, &[inputs_id]);
+        }
+
+        builder
+            .with(cam, cam_id, &[inputs_id])
             .with(bounding_tree, bounding_tree_id, &[])
-            .with(selector, selector_id, &[mouse_id, bounding_tree_id])
+            .with(selector, selector_id, &[inputs_id, bounding_tree_id])
             .with_thread_local(renderer)
             .build()
     };
     dispatcher.setup(&mut world);
 
     world.insert(ResrcDeltaTime(0.0));
-    world.insert(ResrcMouseState::default());
+    world.insert(ResrcInputState::default());
+    world.insert(match &replay_from {
+        Some(path) => ResrcRecording::from_file(path).unwrap(),
+        None => ResrcRecording::default(),
+    });
+    // Bindings can be overridden from the working directory.
+    world.insert(ResrcBindings::from_file_or_default("bindings.json").unwrap());
+    world.insert(ResrcControlGroups::default());
+    world.insert(ResrcSelectionEvents::new());
     world.insert(ResrcBoundingTree::new());
     world.insert(ResrcModelView::default());
     world.insert(ResrcProjection::default());
 
-    let models = rts::voxel::VoxelModel::from_vox(include_bytes!(concat!(
-        env!("CARGO_MANIFEST_DIR"),
-        "/submodules/voxel-model/vox/scan/dragon.vox"
-    )))
+    let scene = vox::spawn_scene(
+        &mut world,
+        include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/submodules/voxel-model/vox/scan/dragon.vox"
+        )),
+    )
     .unwrap();
-    for model in models.into_iter() {
-        world
-            .create_entity()
-            .with(CompVoxelModel(model))
-            .with(CompGridPosition((0, 0, 0).into()))
-            .with(CompInvalidated)
-            .with(CompColor(Color::RED))
-            .build();
-    }
 
     // world
     //     .create_entity()
     //     .with(CompVoxelModel(rts::voxel::VoxelModel::checkerboard()))
     //     .with(CompGridPosition((0, 0, 0).into()))
     //     .with(CompInvalidated)
-    //     .with(CompColor(Color::RED))
     //     .build();
 
     // const TEAPOT_PATH: &str =
@@ -109,6 +126,10 @@ fn main() {
         ResrcCamera::new(inner, ResrcCameraMode::RTS)
     };
     world.insert(cam);
+    // Keep the camera above the scene.
+    if let Some((min, max)) = vox::extent(&world, &scene) {
+        world.write_resource::<ResrcCamera>().set_bounds(min, max);
+    }
 
     #[cfg(target_os = "emscripten")]
     unsafe {
@@ -138,6 +159,13 @@ fn main() {
             dispatcher.dispatch(&mut world);
             world.maintain();
         }
+
+        if let Some(path) = record_to {
+            world
+                .read_resource::<ResrcRecording>()
+                .to_file(path)
+                .unwrap();
+        }
     }
 }
 
@@ -180,6 +208,66 @@ mod vox {
         }
         Ok::<_, AnyError>(())
     }
+
+    /// Spawns one entity per visible model of a `.vox` scene, at the position
+    /// and with the orientation it was authored with.
+    pub fn spawn_scene(world: &mut World, data: &[u8]) -> AnyResult<Vec<Entity>> {
+        let scene = rts::voxel::VoxelModel::from_vox_scene(data)?;
+
+        let entities = scene
+            .models
+            .into_iter()
+            .filter(|m| !m.hidden)
+            .map(|m| {
+                // Grid positions only translate: bake the orientation into
+                // the voxels themselves.
+                let model = if m.rotation == rts::voxel::VoxRotation::default() {
+                    m.model
+                } else {
+                    m.model.rotated(&m.rotation)
+                };
+
+                let mut builder = world
+                    .create_entity()
+                    .with(CompVoxelModel(model))
+                    .with(CompGridPosition(m.position))
+                    .with(CompInvalidated);
+                // Named models are instances of the same unit.
+                if let Some(name) = m.name {
+                    builder = builder.with(CompUnitType(name));
+                }
+                builder.build()
+            })
+            .collect();
+
+        Ok(entities)
+    }
+
+    /// Returns the XZ rectangle covered by the voxels of `entities`, or `None`
+    /// if they're all empty.
+    pub fn extent(world: &World, entities: &[Entity]) -> Option<(Vec2, Vec2)> {
+        let voxels = world.read_storage::<CompVoxelModel>();
+        let grid_pos = world.read_storage::<CompGridPosition>();
+
+        entities
+            .iter()
+            .filter_map(|&e| {
+                let pos = grid_pos.get(e)?.0;
+                let (min, max) = voxels.get(e)?.0.bounds()?;
+                Some((
+                    ((pos.x + min.x) as f32, (pos.z + min.z) as f32),
+                    ((pos.x + max.x + 1) as f32, (pos.z + max.z + 1) as f32),
+                ))
+            })
+            .fold(None, |extent, (min, max)| {
+                let (emin, emax) = extent.unwrap_or((min, max));
+                Some((
+                    (emin.0.min(min.0), emin.1.min(min.1)),
+                    (emax.0.max(max.0), emax.1.max(max.1)),
+                ))
+            })
+            .map(|(min, max)| (min.into(), max.into()))
+    }
 }
 
 // -----------------------------------------------------------------------------
@@ -10,7 +10,7 @@ pub mod prelude {
     pub use super::{
         Color as CompColor, DirectShape as CompDirectShape, GridPosition as CompGridPosition,
         Invalidated as CompInvalidated, Model3D as CompModel3D, Selected as CompSelected,
-        Transform3D as CompTransform3D, VoxelModel as CompVoxelModel,
+        Transform3D as CompTransform3D, UnitType as CompUnitType, VoxelModel as CompVoxelModel,
     };
 }
 
@@ -35,20 +35,37 @@ pub enum DirectShape {
     WireFrame { vertices: Vec<Point3> },
 }
 
-#[derive(Debug, Component)]
-#[storage(HashMapStorage)]
+#[derive(Debug)]
 pub struct VoxelModel(pub RawVoxelModel);
 
 #[derive(Clone, Debug, Component)]
 #[storage(VecStorage)]
 pub struct GridPosition(pub Vec3i);
 
+/// Entities sharing a unit type get selected together on double-click.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Component)]
+#[storage(VecStorage)]
+pub struct UnitType(pub String);
+
 // -----------------------------------------------------------------------------
 
-#[derive(Debug, Component)]
-#[storage(VecStorage)]
+#[derive(Debug)]
 pub struct Model3D(pub Arc<Model>);
 
 #[derive(Clone, Copy, Debug, Component)]
 #[storage(VecStorage)]
 pub struct Transform3D(pub Mat4);
+
+// -----------------------------------------------------------------------------
+
+// Models are flagged so that the bounding tree can find out about deleted
+// ones. Every mutable access gets flagged as a modification: don't ask for
+// one unless there's something to change.
+
+impl Component for VoxelModel {
+    type Storage = FlaggedStorage<Self, HashMapStorage<Self>>;
+}
+
+impl Component for Model3D {
+    type Storage = FlaggedStorage<Self, VecStorage<Self>>;
+}
@@ -1,4 +1,4 @@
-#![feature(bindings_after_at)]
+#![feature(bindings_after_at, slice_partition_at_index)]
 
 // -----------------------------------------------------------------------------
 
@@ -2,7 +2,7 @@ use cgmath::{
     Matrix4 as CGMat4, Point3 as CGPoint3, Quaternion as CGQuat, Vector2 as CGVec2,
     Vector3 as CGVec3, Vector4 as CGVec4,
 };
-use collision::Ray3 as CGRay3;
+use collision::{Frustum, Plane, Ray3 as CGRay3};
 use raylib::{
     core::math::{
         Matrix as RMat4, Quaternion as RQuat, Ray as RRay3, Vector2 as RVec2, Vector3 as RVec3,
@@ -66,6 +66,58 @@ where
     }
 }
 
+/* Frustum extraction */
+
+impl Mat4 {
+    /// Extracts the frustum of a view-projection matrix, planes facing
+    /// inwards (Gribb & Hartmann).
+    ///
+    /// Returns `None` if the matrix is degenerate.
+    pub fn to_frustum(&self) -> Option<Frustum<f32>> {
+        let m = &self.0;
+        let plane = |v: CGVec4<f32>| Plane::new(CGVec3::new(v.x, v.y, v.z), -v.w).normalize();
+        let (r0, r1, r2, r3) = (m.row(0), m.row(1), m.row(2), m.row(3));
+
+        Some(Frustum::new(
+            plane(r3 + r0)?,
+            plane(r3 - r0)?,
+            plane(r3 + r1)?,
+            plane(r3 - r1)?,
+            plane(r3 + r2)?,
+            plane(r3 - r2)?,
+        ))
+    }
+
+    /// Extracts the sub-frustum of a view-projection matrix that covers the
+    /// rectangle going from `min` to `max` on a screen of size `screen`, in
+    /// pixels, origin at the top-left.
+    ///
+    /// Returns `None` if the rectangle or the matrix is degenerate.
+    pub fn to_screen_frustum(&self, screen: Vec2, min: Vec2, max: Vec2) -> Option<Frustum<f32>> {
+        let to_ndc = |p: &Vec2| (2. * p.x / screen.x - 1., 1. - 2. * p.y / screen.y);
+        let (x1, y1) = to_ndc(&min);
+        let (x2, y2) = to_ndc(&max);
+        let (x_min, x_max) = (x1.min(x2), x1.max(x2));
+        let (y_min, y_max) = (y1.min(y2), y1.max(y2));
+        let (width, height) = (x_max - x_min, y_max - y_min);
+        if width <= 0. || height <= 0. {
+            return None;
+        }
+
+        // Stretches the rectangle over the whole clip space, so that its
+        // frustum can be extracted as usual.
+        #[rustfmt::skip]
+        let stretch = CGMat4::new(
+            2. / width,               0.,                        0., 0.,
+            0.,                       2. / height,               0., 0.,
+            0.,                       0.,                        1., 0.,
+            -(x_min + x_max) / width, -(y_min + y_max) / height, 0., 1.,
+        );
+
+        Mat4(stretch * self.0).to_frustum()
+    }
+}
+
 /* Instantiate from Raylib Rust */
 
 // impl From<RMat4> for Mat4 {
@@ -559,3 +611,111 @@ impl Into<RawVec4> for Vec4 {
         }
     }
 }
+
+// -----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collision::{PlaneBound, Relation};
+
+    // Looks down -Z from `(0, 0, 10)`: with a square screen and a 90° field
+    // of view, the frustum is as wide as it is deep.
+    fn view_proj() -> Mat4 {
+        let view = CGMat4::look_at(
+            CGPoint3::new(0., 0., 10.),
+            CGPoint3::new(0., 0., 0.),
+            CGVec3::new(0., 1., 0.),
+        );
+        let proj = cgmath::perspective(cgmath::Deg(90.), 1., 1., 100.);
+
+        (proj * view).into()
+    }
+
+    fn planes(f: &Frustum<f32>) -> [(&'static str, Plane<f32>); 6] {
+        [
+            ("left", f.left),
+            ("right", f.right),
+            ("bottom", f.bottom),
+            ("top", f.top),
+            ("near", f.near),
+            ("far", f.far),
+        ]
+    }
+
+    // Asserts that `p` lies outside of the `outside` plane only, if any.
+    fn assert_relations(f: &Frustum<f32>, p: (f32, f32, f32), outside: Option<&str>) {
+        let p = CGPoint3::new(p.0, p.1, p.2);
+        for (name, plane) in planes(f).iter() {
+            let expected = if Some(*name) == outside {
+                Relation::Out
+            } else {
+                Relation::In
+            };
+            assert_eq!(expected, p.relate_plane(*plane), "{:?} vs. {}", p, name);
+        }
+
+        let expected = outside.map_or(Relation::In, |_| Relation::Out);
+        assert_eq!(expected, f.contains(&p), "{:?}", p);
+    }
+
+    #[test]
+    fn to_frustum() {
+        let f = view_proj().to_frustum().unwrap();
+
+        assert_relations(&f, (0., 0., 0.), None);
+        assert_relations(&f, (9., -9., 0.), None);
+        assert_relations(&f, (0., 0., -85.), None);
+
+        assert_relations(&f, (-11., 0., 0.), Some("left"));
+        assert_relations(&f, (11., 0., 0.), Some("right"));
+        assert_relations(&f, (0., -11., 0.), Some("bottom"));
+        assert_relations(&f, (0., 11., 0.), Some("top"));
+        assert_relations(&f, (0., 0., 9.5), Some("near"));
+        assert_relations(&f, (0., 0., -95.), Some("far"));
+
+        // Behind the camera.
+        assert_eq!(Relation::Out, f.contains(&CGPoint3::new(0., 0., 20.)));
+    }
+
+    #[test]
+    fn to_screen_frustum() {
+        let screen: Vec2 = (100., 100.).into();
+        let (min, max) = ((25., 25.).into(), (75., 75.).into());
+        let f = view_proj().to_screen_frustum(screen, min, max).unwrap();
+
+        // At a depth of 10, the screen spans [-10, 10] on both axes, Y up.
+        assert_relations(&f, (0., 0., 0.), None);
+        assert_relations(&f, (-4., 4., 0.), None);
+        assert_relations(&f, (-8., 0., 0.), Some("left"));
+        assert_relations(&f, (8., 0., 0.), Some("right"));
+        assert_relations(&f, (0., -8., 0.), Some("bottom"));
+        assert_relations(&f, (0., 8., 0.), Some("top"));
+        assert_relations(&f, (0., 0., 9.5), Some("near"));
+        assert_relations(&f, (0., 0., -95.), Some("far"));
+
+        // The top-left corner of the screen, well within the full frustum.
+        assert_eq!(Relation::Out, f.contains(&CGPoint3::new(-8., 8., 0.)));
+
+        // Rectangles can be given corner to corner, in any order.
+        let g = view_proj().to_screen_frustum(screen, max, min).unwrap();
+        assert_eq!(Relation::In, g.contains(&CGPoint3::new(0., 0., 0.)));
+        assert_eq!(Relation::Out, g.contains(&CGPoint3::new(-8., 8., 0.)));
+    }
+
+    #[test]
+    fn to_screen_frustum_degenerate() {
+        let screen: Vec2 = (100., 100.).into();
+        let vp = view_proj();
+
+        assert!(vp
+            .to_screen_frustum(screen, (50., 50.).into(), (50., 50.).into())
+            .is_none());
+        assert!(vp
+            .to_screen_frustum(screen, (25., 50.).into(), (75., 50.).into())
+            .is_none());
+        assert!(vp
+            .to_screen_frustum(screen, (50., 25.).into(), (50., 75.).into())
+            .is_none());
+    }
+}
@@ -0,0 +1,316 @@
+use crate::resources::{inputs::InputState, keyboard::KeyboardState};
+use anyhow::{anyhow, Error as AnyError, Result as AnyResult};
+use raylib::consts::KeyboardKey;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    io,
+    path::Path,
+};
+
+// -----------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    CameraForward,
+    CameraBackward,
+    CameraLeft,
+    CameraRight,
+    CameraRotateLeft,
+    CameraRotateRight,
+    CameraLook,
+    CameraPan,
+    CameraModeRts,
+    CameraModeFree,
+    CameraModeOrbital,
+    SelectBox,
+    SelectAdd,
+    SelectToggle,
+    SelectSubtract,
+    /// Held while recalling a control group to assign it instead.
+    ControlGroupAssign,
+    ControlGroup1,
+    ControlGroup2,
+    ControlGroup3,
+    ControlGroup4,
+    ControlGroup5,
+    ControlGroup6,
+    ControlGroup7,
+    ControlGroup8,
+    ControlGroup9,
+}
+
+impl Action {
+    /// Recalls control groups 1 to 9, in order.
+    pub const CONTROL_GROUPS: [Action; 9] = [
+        Action::ControlGroup1,
+        Action::ControlGroup2,
+        Action::ControlGroup3,
+        Action::ControlGroup4,
+        Action::ControlGroup5,
+        Action::ControlGroup6,
+        Action::ControlGroup7,
+        Action::ControlGroup8,
+        Action::ControlGroup9,
+    ];
+}
+
+/// A single key or mouse button, serialized by name (e.g. `"W"`,
+/// `"LeftShift"`, `"MouseLeft"`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Input {
+    Key(KeyboardKey),
+    /// Indexed as in `MouseState`.
+    Mouse(usize),
+}
+
+impl Input {
+    const MOUSE_BUTTONS: &'static [&'static str] = &["MouseLeft", "MouseRight", "MouseMiddle"];
+
+    /// Every input that can be referred to by name.
+    pub fn all() -> impl Iterator<Item = Input> {
+        let keys = KeyboardState::KEYS.iter().map(|&(_, key)| Input::Key(key));
+        let buttons = (0..Self::MOUSE_BUTTONS.len()).map(Input::Mouse);
+        keys.chain(buttons)
+    }
+
+    pub fn is_down(&self, inputs: &InputState) -> bool {
+        match *self {
+            Input::Key(key) => inputs.keyboard().is_down(key),
+            Input::Mouse(button) => inputs.mouse().is_down(button),
+        }
+    }
+
+    pub fn is_pressed(&self, inputs: &InputState) -> bool {
+        match *self {
+            Input::Key(key) => inputs.keyboard().is_pressed(key),
+            Input::Mouse(button) => inputs.mouse().is_pressed(button),
+        }
+    }
+
+    pub fn is_released(&self, inputs: &InputState) -> bool {
+        match *self {
+            Input::Key(key) => inputs.keyboard().is_released(key),
+            Input::Mouse(button) => inputs.mouse().is_released(button),
+        }
+    }
+
+    pub fn set(&self, inputs: &mut InputState, pressed: bool, released: bool, down: bool) {
+        match *self {
+            Input::Key(key) => inputs.keyboard_mut().set(key, pressed, released, down),
+            Input::Mouse(button) => inputs
+                .mouse_mut()
+                .set_button(button, pressed, released, down),
+        }
+    }
+}
+
+impl TryFrom<String> for Input {
+    type Error = AnyError;
+
+    fn try_from(name: String) -> AnyResult<Self> {
+        if let Some(button) = Self::MOUSE_BUTTONS.iter().position(|&b| b == name) {
+            return Ok(Input::Mouse(button));
+        }
+        KeyboardState::key_from_name(&name)
+            .map(Input::Key)
+            .ok_or_else(|| anyhow!("unknown input: {:?}", name))
+    }
+}
+
+impl From<Input> for String {
+    fn from(input: Input) -> Self {
+        match input {
+            Input::Key(key) => KeyboardState::key_name(key)
+                .map(ToOwned::to_owned)
+                .unwrap_or_else(|| format!("{:?}", key)),
+            Input::Mouse(button) => Input::MOUSE_BUTTONS
+                .get(button)
+                .map(|&b| b.to_owned())
+                .unwrap_or_else(|| format!("Mouse{}", button)),
+        }
+    }
+}
+
+/// Inputs that must all be held together, e.g. `["LeftControl", "S"]`.
+pub type Chord = Vec<Input>;
+
+// -----------------------------------------------------------------------------
+
+/// Maps actions to the chords that trigger them; an action can have any
+/// number of chords.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Bindings(HashMap<Action, Vec<Chord>>);
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self::from_json(Self::DEFAULT).unwrap()
+    }
+}
+
+impl Bindings {
+    const DEFAULT: &'static str = include_str!("../../assets/bindings.json");
+
+    pub fn from_json(json: &str) -> AnyResult<Self> {
+        serde_json::from_str(json).map_err(Into::into)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> AnyResult<Self> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Same as `from_file`, except that a missing file yields the default
+    /// bindings; a malformed one is still an error.
+    pub fn from_file_or_default(path: impl AsRef<Path>) -> AnyResult<Self> {
+        let path = path.as_ref();
+        match std::fs::read_to_string(path) {
+            Ok(json) => {
+                Self::from_json(&json).map_err(|err| anyhow!("{}: {}", path.display(), err))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        // Sorted, so that saved bindings can be diffed.
+        let sorted: BTreeMap<_, _> = self.0.iter().collect();
+        // Serializing plain maps & strings cannot fail.
+        serde_json::to_string_pretty(&sorted).unwrap()
+    }
+
+    pub fn chords(&self, action: Action) -> &[Chord] {
+        self.0.get(&action).map_or(&[], |chords| chords.as_slice())
+    }
+
+    /// Returns the first mouse button found among the chords of `action`,
+    /// if any, for those who care about clicks & drags.
+    pub fn mouse_button(&self, action: Action) -> Option<usize> {
+        self.chords(action).iter().flatten().find_map(|i| match *i {
+            Input::Mouse(button) => Some(button),
+            Input::Key(_) => None,
+        })
+    }
+
+    /// Adds `chord` to the ones already bound to `action`.
+    pub fn bind(&mut self, action: Action, chord: Chord) {
+        let chords = self.0.entry(action).or_default();
+        if !chords.contains(&chord) {
+            chords.push(chord);
+        }
+    }
+
+    /// Replaces every chord bound to `action` with `chord`.
+    pub fn rebind(&mut self, action: Action, chord: Chord) {
+        self.0.insert(action, vec![chord]);
+    }
+
+    pub fn unbind(&mut self, action: Action) {
+        self.0.remove(&action);
+    }
+}
+
+impl Bindings {
+    /// True while every input of any chord of `action` is held.
+    pub fn is_down(&self, inputs: &InputState, action: Action) -> bool {
+        self.chords(action)
+            .iter()
+            .any(|chord| !chord.is_empty() && chord.iter().all(|i| i.is_down(inputs)))
+    }
+
+    /// True on the frame a chord of `action` gets completed.
+    pub fn is_pressed(&self, inputs: &InputState, action: Action) -> bool {
+        self.chords(action).iter().any(|chord| {
+            chord.iter().any(|i| i.is_pressed(inputs)) && chord.iter().all(|i| i.is_down(inputs))
+        })
+    }
+
+    /// True on the frame a completed chord of `action` gets broken.
+    pub fn is_released(&self, inputs: &InputState, action: Action) -> bool {
+        self.chords(action).iter().any(|chord| {
+            chord.iter().any(|i| i.is_released(inputs))
+                && chord
+                    .iter()
+                    .all(|i| i.is_down(inputs) || i.is_released(inputs))
+        })
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raylib::consts::KeyboardKey::*;
+
+    #[test]
+    fn default_round_trip() {
+        let bindings = Bindings::default();
+        for &action in &[
+            Action::CameraForward,
+            Action::CameraModeRts,
+            Action::SelectBox,
+            Action::SelectSubtract,
+            Action::ControlGroupAssign,
+            Action::ControlGroup9,
+        ] {
+            assert!(
+                !bindings.chords(action).is_empty(),
+                "{:?} is unbound",
+                action
+            );
+        }
+
+        let json = bindings.to_json();
+        let reloaded = Bindings::from_json(&json).unwrap();
+        assert_eq!(bindings.0, reloaded.0);
+        assert_eq!(json, reloaded.to_json());
+    }
+
+    #[test]
+    fn malformed_files_are_errors() {
+        assert!(Bindings::from_json(r#"{ "CameraForward": [["NotAKey"]] }"#).is_err());
+        assert!(Bindings::from_json(r#"{ "NotAnAction": [["W"]] }"#).is_err());
+
+        let path = std::env::temp_dir().join("rts-malformed-bindings.json");
+        std::fs::write(&path, "{ not json").unwrap();
+        assert!(Bindings::from_file_or_default(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        // Only a missing file falls back to the defaults.
+        assert!(Bindings::from_file_or_default(&path).is_ok());
+    }
+
+    #[test]
+    fn chords_need_every_input() {
+        let mut bindings = Bindings::from_json("{}").unwrap();
+        let save = Input::try_from("LeftControl".to_owned()).unwrap();
+        let s = Input::try_from("S".to_owned()).unwrap();
+        assert_eq!((Input::Key(KEY_LEFT_CONTROL), Input::Key(KEY_S)), (save, s));
+        bindings.bind(Action::SelectAdd, vec![save, s]);
+
+        let mut inputs = InputState::default();
+        assert!(!bindings.is_down(&inputs, Action::SelectAdd));
+
+        save.set(&mut inputs, true, false, true);
+        assert!(!bindings.is_down(&inputs, Action::SelectAdd));
+        assert!(!bindings.is_pressed(&inputs, Action::SelectAdd));
+
+        s.set(&mut inputs, true, false, true);
+        save.set(&mut inputs, false, false, true);
+        assert!(bindings.is_down(&inputs, Action::SelectAdd));
+        assert!(bindings.is_pressed(&inputs, Action::SelectAdd));
+
+        save.set(&mut inputs, false, true, false);
+        assert!(!bindings.is_down(&inputs, Action::SelectAdd));
+        assert!(bindings.is_released(&inputs, Action::SelectAdd));
+
+        s.set(&mut inputs, false, false, true);
+        save.set(&mut inputs, false, false, false);
+        assert!(!bindings.is_down(&inputs, Action::SelectAdd));
+        assert!(!bindings.is_released(&inputs, Action::SelectAdd));
+    }
+}
@@ -7,7 +7,7 @@ use collision::{
     Aabb3, Frustum, Ray3,
 };
 use specs::{prelude::*, WorldExt};
-use std::{cell::UnsafeCell, collections::HashMap};
+use std::collections::HashMap;
 
 // -----------------------------------------------------------------------------
 
@@ -39,7 +39,8 @@ impl TreeValue for BoundingValue {
 #[derive(Debug)]
 pub struct BoundingTree {
     inner: DynamicBoundingVolumeTree<BoundingValue>,
-    entity_mappings: HashMap<Entity, usize>,
+    // Bounds are duplicated here so that they can be looked up by entity.
+    entity_mappings: HashMap<Entity, (usize, Aabb3<f32>)>,
 }
 
 impl BoundingTree {
@@ -55,17 +56,40 @@ impl BoundingTree {
 
 impl BoundingTree {
     pub fn update_entity(&mut self, e: Entity, min: Point3, max: Point3) {
-        let this = UnsafeCell::new(self);
-        let mutself = move || -> &mut Self { unsafe { *this.get() } };
-        mutself()
-            .entity_mappings
-            .entry(e)
-            .and_modify(|idx| {
-                mutself()
-                    .inner
-                    .update_node(*idx, BoundingValue::new(e, min, max))
-            })
-            .or_insert_with(|| mutself().inner.insert(BoundingValue::new(e, min, max)));
+        let value = BoundingValue::new(e, min, max);
+        let aabb = value.aabb;
+        match self.entity_mappings.get_mut(&e) {
+            Some((idx, bounds)) => {
+                self.inner.update_node(*idx, value);
+                *bounds = aabb;
+            }
+            None => {
+                let idx = self.inner.insert(value);
+                self.entity_mappings.insert(e, (idx, aabb));
+            }
+        }
+    }
+
+    /// Removes `e` from the tree, if it was in there.
+    pub fn remove_entity(&mut self, e: Entity) {
+        // `DynamicBoundingVolumeTree::remove` swap-removes the value out of
+        // `values()` and frees the leaf, but never moves any other node:
+        // the indices of the remaining leaves stay valid.
+        if let Some((idx, _)) = self.entity_mappings.remove(&e) {
+            self.inner.remove(idx);
+        }
+    }
+
+    /// Returns the bounds of `e`, if it's in the tree.
+    pub fn bounds(&self, e: Entity) -> Option<(Point3, Point3)> {
+        self.entity_mappings
+            .get(&e)
+            .map(|(_, aabb)| (aabb.min.into(), aabb.max.into()))
+    }
+
+    /// Iterates over all the entities currently in the tree.
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.entity_mappings.keys().copied()
     }
 
     pub fn refresh(&mut self) {
@@ -79,8 +103,161 @@ impl BoundingTree {
         self.inner.query(&mut vis).into_iter().map(|(bv, _)| bv.e)
     }
 
-    pub fn test_ray(&self, r: &Ray3<f32>) -> impl Iterator<Item = Entity> + '_ {
+    /// Returns the entities hit by `r`, along with the distance from the
+    /// origin of the ray to their bounds, closest first.
+    pub fn test_ray(&self, r: &Ray3<f32>) -> Vec<(Entity, f32)> {
         let mut vis = ContinuousVisitor::<_, BoundingValue>::new(r);
+        let mut hits: Vec<_> = self
+            .inner
+            .query(&mut vis)
+            .into_iter()
+            .map(|(bv, hit)| (bv.e, (hit - r.origin).magnitude()))
+            .collect();
+        sort_by_distance(&mut hits);
+
+        hits
+    }
+
+    /// Returns the entities whose bounds overlap the box delimited by `min`
+    /// and `max`.
+    pub fn test_aabb(&self, min: Point3, max: Point3) -> impl Iterator<Item = Entity> + '_ {
+        let aabb = Aabb3::new(*min, *max);
+        let mut vis = DiscreteVisitor::<_, BoundingValue>::new(&aabb);
         self.inner.query(&mut vis).into_iter().map(|(bv, _)| bv.e)
     }
+
+    /// Returns the entities whose bounds lie within `radius` of `center`,
+    /// along with their distance to it, closest first.
+    pub fn test_sphere(&self, center: Point3, radius: f32) -> Vec<(Entity, f32)> {
+        let extent = CGVec3::new(radius, radius, radius);
+        let aabb = Aabb3::new(*center - extent, *center + extent);
+        let mut vis = DiscreteVisitor::<_, BoundingValue>::new(&aabb);
+
+        // The box query is only a broad phase: its corners lie further away
+        // than `radius`.
+        let mut hits: Vec<_> = self
+            .inner
+            .query(&mut vis)
+            .into_iter()
+            .map(|(bv, _)| (bv.e, distance_to_aabb(&bv.aabb, &center)))
+            .filter(|&(_, dist)| dist <= radius)
+            .collect();
+        sort_by_distance(&mut hits);
+
+        hits
+    }
+
+    /// Returns the (at most) `k` entities closest to `point` that satisfy
+    /// `filter`, along with their distance to it, closest first.
+    ///
+    /// This is a linear scan over every entity in the tree, i.e. O(n).
+    pub fn nearest(
+        &self,
+        point: Point3,
+        k: usize,
+        filter: impl Fn(Entity) -> bool,
+    ) -> Vec<(Entity, f32)> {
+        // Visitors only ever get to see bounds, not entities: the tree cannot
+        // be pruned without knowing what `filter` keeps.
+        let mut hits: Vec<_> = self
+            .inner
+            .values()
+            .iter()
+            .filter(|(_, bv)| filter(bv.e))
+            .map(|(_, bv)| (bv.e, distance_to_aabb(&bv.aabb, &point)))
+            .collect();
+        // Only the `k` closest hits need sorting.
+        if k < hits.len() {
+            hits.partition_at_index_by(k, cmp_distance);
+            hits.truncate(k);
+        }
+        sort_by_distance(&mut hits);
+
+        hits
+    }
+}
+
+/// Distance from `p` to the closest point of `aabb`; zero if `p` lies within.
+fn distance_to_aabb(aabb: &Aabb3<f32>, p: &Point3) -> f32 {
+    let clamped = CGPoint3::new(
+        p.x.max(aabb.min.x).min(aabb.max.x),
+        p.y.max(aabb.min.y).min(aabb.max.y),
+        p.z.max(aabb.min.z).min(aabb.max.z),
+    );
+    (clamped - **p).magnitude()
+}
+
+fn cmp_distance((_, d1): &(Entity, f32), (_, d2): &(Entity, f32)) -> std::cmp::Ordering {
+    d1.partial_cmp(d2).unwrap_or(std::cmp::Ordering::Equal)
+}
+
+fn sort_by_distance(hits: &mut [(Entity, f32)]) {
+    hits.sort_by(cmp_distance);
+}
+
+// -----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest_returns_k_closest_sorted() {
+        let mut world = World::new();
+        let mut bt = BoundingTree::new();
+        // Unit boxes one unit apart along X, inserted out of order.
+        let entities: Vec<_> = [7, 2, 9, 0, 5, 3, 8, 1, 6, 4]
+            .iter()
+            .map(|&i| {
+                let e = world.create_entity().build();
+                let x = i as f32 * 2.;
+                bt.update_entity(e, (x, 0., 0.).into(), (x + 1., 1., 1.).into());
+                (i, e)
+            })
+            .collect();
+        let entity = |i| entities.iter().find(|(j, _)| *j == i).unwrap().1;
+
+        let hits = bt.nearest((-1., 0., 0.).into(), 3, |_| true);
+        let expected = vec![(entity(0), 1.), (entity(1), 3.), (entity(2), 5.)];
+        assert_eq!(expected, hits);
+
+        let odd = |e| entities.iter().any(|&(i, e2)| e2 == e && i % 2 == 1);
+        let hits: Vec<_> = bt
+            .nearest((-1., 0., 0.).into(), 3, odd)
+            .into_iter()
+            .map(|(e, _)| e)
+            .collect();
+        assert_eq!(vec![entity(1), entity(3), entity(5)], hits);
+
+        assert_eq!(10, bt.nearest((-1., 0., 0.).into(), 100, |_| true).len());
+        assert!(bt.nearest((-1., 0., 0.).into(), 0, |_| true).is_empty());
+    }
+
+    #[test]
+    fn test_sphere_narrow_phase() {
+        let mut world = World::new();
+        let mut bt = BoundingTree::new();
+        let mut spawn = |min: (f32, f32, f32), max: (f32, f32, f32)| {
+            let e = world.create_entity().build();
+            bt.update_entity(e, min.into(), max.into());
+            e
+        };
+        let around = spawn((-1., -1., -1.), (1., 1., 1.));
+        let side = spawn((2., 0., 0.), (3., 1., 1.));
+        // Within the broad-phase box, yet sqrt(12) away.
+        let corner = spawn((2., 2., 2.), (3., 3., 3.));
+        let far = spawn((10., 0., 0.), (11., 1., 1.));
+        bt.refresh();
+
+        let hits = bt.test_sphere((0., 0., 0.).into(), 2.5);
+        assert_eq!(vec![(around, 0.), (side, 2.)], hits);
+
+        let hits: Vec<_> = bt
+            .test_sphere((0., 0., 0.).into(), 3.5)
+            .into_iter()
+            .map(|(e, _)| e)
+            .collect();
+        assert_eq!(vec![around, side, corner], hits);
+        assert!(!hits.contains(&far));
+    }
 }
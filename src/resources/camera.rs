@@ -1,4 +1,5 @@
 use crate::{components::prelude::*, maths::prelude::*, resources::prelude::*};
+use collision::Ray3;
 use raylib::prelude::*;
 use specs::{prelude::*, WorldExt};
 
@@ -17,6 +18,8 @@ pub struct Camera {
 
     updates_prev: Option<Updates>,
     updater: Box<dyn Updater + Send + Sync + 'static>,
+
+    bounds: Option<(Vec2, Vec2)>,
 }
 
 impl Camera {
@@ -27,6 +30,7 @@ impl Camera {
             mode,
             updates_prev: None,
             updater,
+            bounds: None,
         }
     }
 
@@ -37,32 +41,107 @@ impl Camera {
     pub fn mode(&self) -> Mode {
         self.mode
     }
+
+    /// Moves the camera so that it looks at `target`, if the current mode
+    /// allows for it.
+    pub fn focus(&mut self, target: Point3) {
+        self.updater.focus(&target);
+    }
+
+    /// Keeps the center of the screen within the `[min, max]` rectangle of
+    /// the XZ plane, if the current mode allows for it.
+    ///
+    /// The bounds outlive mode switches.
+    pub fn set_bounds(&mut self, min: Vec2, max: Vec2) {
+        self.bounds = Some((min, max));
+        self.updater.set_bounds(min, max);
+    }
 }
 
+// Projections are computed from the state of the camera alone, mirroring what
+// raylib does in `BeginMode3D`, so that they don't depend on a live window.
 impl Camera {
-    pub fn update(&mut self, rl: &ResrcRaylib, delta: &ResrcDeltaTime) {
-        // TODO(cmc): kbd state
-        rl.read(|rl| {
-            if rl.is_key_released(KeyboardKey::KEY_F1) {
-                self.mode = Mode::RTS;
-                self.updater = Box::new(updaters::RTS::default());
-            }
-            if rl.is_key_released(KeyboardKey::KEY_F2) {
-                self.mode = Mode::FREE;
-                self.updater = Box::new(updaters::Free::default());
-            }
-            if rl.is_key_released(KeyboardKey::KEY_F3) {
-                self.mode = Mode::ORBITAL;
-                self.updater = Box::new(updaters::Orbital::default());
-            }
-        });
+    const FAR: f32 = 1000.0;
+    // Raylib's `RL_CULL_DISTANCE_NEAR` & `RL_CULL_DISTANCE_FAR`.
+    const NEAR: f32 = 0.01;
+
+    /// Returns the view-projection matrix for a screen of size `screen`, in
+    /// pixels.
+    pub fn view_projection(&self, screen: Vec2) -> Mat4 {
+        let to_point = |v: Vector3| CGPoint3::new(v.x, v.y, v.z);
+        let view = CGMat4::look_at(
+            to_point(self.inner.position),
+            to_point(self.inner.target),
+            CGVec3::new(self.inner.up.x, self.inner.up.y, self.inner.up.z),
+        );
+        let proj = cgmath::perspective(
+            cgmath::Deg(self.inner.fovy),
+            // Don't blow up on a minimized window.
+            screen.x.max(1.) / screen.y.max(1.),
+            Self::NEAR,
+            Self::FAR,
+        );
+
+        (proj * view).into()
+    }
 
-        let updates = if rl.read(|rl| rl.is_mouse_button_up(MouseButton::MOUSE_LEFT_BUTTON)) {
-            Updates::from_input(rl, None)
-        } else {
-            Updates::from_input(rl, self.updates_prev.as_ref())
+    /// Returns the ray going from the near plane to the far plane through the
+    /// pixel at `pos`, origin at the top-left of the screen, or `None` if the
+    /// camera is degenerate (e.g. its target is its position).
+    pub fn screen_to_ray(&self, pos: Vec2, screen: Vec2) -> Option<Ray3<f32>> {
+        // A degenerate `look_at` yields NaNs rather than a singular matrix.
+        let inv = self
+            .view_projection(screen)
+            .invert()
+            .filter(CGMat4::is_finite)?;
+        let (x, y) = (2. * pos.x / screen.x - 1., 1. - 2. * pos.y / screen.y);
+        let unproject = |z: f32| {
+            let p = inv * CGVec4::new(x, y, z, 1.);
+            CGPoint3::new(p.x / p.w, p.y / p.w, p.z / p.w)
         };
 
+        let (near, far) = (unproject(-1.), unproject(1.));
+        Some(Ray3::new(near, (far - near).normalize()))
+    }
+
+    /// Returns the pixel that `point` projects onto, origin at the top-left
+    /// of the screen, or `None` if it lies behind the camera.
+    pub fn world_to_screen(&self, point: Point3, screen: Vec2) -> Option<Vec2> {
+        let p = *self.view_projection(screen) * point.to_homogeneous();
+        if p.w <= 0. {
+            return None;
+        }
+
+        let (x, y) = (p.x / p.w, p.y / p.w);
+        Some(((x + 1.) / 2. * screen.x, (1. - y) / 2. * screen.y).into())
+    }
+}
+
+impl Camera {
+    pub fn update(
+        &mut self,
+        inputs: &ResrcInputState,
+        bindings: &ResrcBindings,
+        delta: &ResrcDeltaTime,
+    ) {
+        if bindings.is_released(inputs, Action::CameraModeRts) {
+            self.mode = Mode::RTS;
+            self.updater = Box::new(updaters::RTS::default());
+            if let Some((min, max)) = self.bounds {
+                self.updater.set_bounds(min, max);
+            }
+        }
+        if bindings.is_released(inputs, Action::CameraModeFree) {
+            self.mode = Mode::FREE;
+            self.updater = Box::new(updaters::Free::default());
+        }
+        if bindings.is_released(inputs, Action::CameraModeOrbital) {
+            self.mode = Mode::ORBITAL;
+            self.updater = Box::new(updaters::Orbital::default());
+        }
+
+        let updates = Updates::from_input(inputs, bindings, self.updates_prev.as_ref());
+
         let (pos, target) = self.updater.update(delta, &updates);
         self.updates_prev = updates.into();
 
@@ -75,6 +154,10 @@ impl Camera {
 
 trait Updater {
     fn update(&mut self, delta: &ResrcDeltaTime, updates: &Updates) -> (Vec3, Point3);
+
+    fn focus(&mut self, _target: &Point3) {}
+
+    fn set_bounds(&mut self, _min: Vec2, _max: Vec2) {}
 }
 
 #[derive(Debug, Clone)]
@@ -90,31 +173,48 @@ struct Updates {
     rot_down: bool,
 
     mouse_pos: Vec2,
+    /// `mouse_pos` is stale otherwise.
+    cursor_inside: bool,
+    /// Only set while looking around.
     mouse_diff: Vec2,
+    /// Only set while panning.
+    pan: Vec2,
+
+    screen_size: Vec2,
 
     zoom: i32,
 }
 
 impl Updates {
-    // TODO(cmc): kbd state + no rl ref
-    pub fn from_input(rl: &ResrcRaylib, prev: Option<&Self>) -> Self {
-        let ((mov_left, mov_up, mov_right, mov_down), zoom) = rl.read(|rl| {
-            let dir = (
-                rl.is_key_down(KeyboardKey::KEY_A),
-                rl.is_key_down(KeyboardKey::KEY_W),
-                rl.is_key_down(KeyboardKey::KEY_D),
-                rl.is_key_down(KeyboardKey::KEY_S),
-            );
-            let zoom = rl.get_mouse_wheel_move();
-
-            (dir, zoom)
-        });
-
-        let mouse_pos = rl.read(|rl| rl.get_mouse_position());
-        let mouse_pos: Vec2 = (mouse_pos.x, mouse_pos.y).into();
-        let mouse_diff = prev
+    pub fn from_input(
+        inputs: &ResrcInputState,
+        bindings: &ResrcBindings,
+        prev: Option<&Self>,
+    ) -> Self {
+        let (mov_left, mov_up, mov_right, mov_down) = (
+            bindings.is_down(inputs, Action::CameraLeft),
+            bindings.is_down(inputs, Action::CameraForward),
+            bindings.is_down(inputs, Action::CameraRight),
+            bindings.is_down(inputs, Action::CameraBackward),
+        );
+        let zoom = inputs.mouse().wheel();
+
+        let mouse_pos = inputs.mouse().position();
+        let mouse_pos: Vec2 = (mouse_pos.x as f32, mouse_pos.y as f32).into();
+        let diff: Vec2 = prev
             .map_or_else(|| (0., 0.).into(), |u| *mouse_pos - *u.mouse_pos)
             .into();
+        let only_if = |action| {
+            if bindings.is_down(inputs, action) {
+                diff
+            } else {
+                (0., 0.).into()
+            }
+        };
+        let (mouse_diff, pan) = (only_if(Action::CameraLook), only_if(Action::CameraPan));
+
+        let screen_size = inputs.screen_size();
+        let screen_size = (screen_size.x as f32, screen_size.y as f32).into();
 
         Self {
             mov_left,
@@ -122,13 +222,17 @@ impl Updates {
             mov_right,
             mov_down,
 
-            rot_left: false,
+            rot_left: bindings.is_down(inputs, Action::CameraRotateLeft),
             rot_up: false,
-            rot_right: false,
+            rot_right: bindings.is_down(inputs, Action::CameraRotateRight),
             rot_down: false,
 
             mouse_pos,
+            cursor_inside: inputs.is_cursor_inside(),
             mouse_diff,
+            pan,
+
+            screen_size,
 
             zoom,
         }
@@ -140,56 +244,144 @@ mod updaters {
 
     // -----------------------------------------------------------------------------
 
+    /// Every input moves the goal state; the actual state then eases towards
+    /// it, exponentially.
     #[derive(Debug, Clone)]
     pub struct RTS {
         pos: Vec3,
         x_rad: f32,
         y_rad: f32,
+
+        goal_pos: Vec3,
+        goal_x_rad: f32,
+        goal_y_rad: f32,
+
         radius: f32,
         speed_multiplier: f32,
+        bounds: Option<(Vec2, Vec2)>,
     }
 
     impl RTS {
+        /// Distance from the borders of the screen at which the cursor starts
+        /// scrolling, in pixels.
+        const EDGE_MARGIN: f32 = 10.0;
+        /// World units travelled per pixel dragged.
+        const PAN_SPEED: f32 = 0.25;
         const PI: f32 = consts::PI as f32;
+        /// Radians per second.
+        const ROTATION_SPEED: f32 = Self::PI / 2.;
+        /// The higher, the snappier.
+        const SMOOTHING: f32 = 10.0;
         const Y: f32 = 30.0;
+
+        fn height(y_rad: f32) -> f32 {
+            Self::Y * 2. * y_rad.abs()
+        }
+
+        /// Horizontal distance between the goal position and the point of the
+        /// ground that it looks at, if it looks down at all.
+        fn goal_ground_distance(&self) -> f32 {
+            if self.goal_y_rad < 0. {
+                Self::height(self.goal_y_rad) * self.goal_y_rad.cos() / -self.goal_y_rad.sin()
+            } else {
+                0.
+            }
+        }
+
+        /// Clamps whatever the goal looks at, rather than the goal itself, so
+        /// that rotating doesn't get stuck on the borders.
+        fn clamp_goal(&mut self) {
+            if let Some((min, max)) = self.bounds {
+                let dist = self.goal_ground_distance();
+                let (sin, cos) = self.goal_x_rad.sin_cos();
+                let x = (self.goal_pos.x + dist * sin).max(min.x).min(max.x);
+                let z = (self.goal_pos.z + dist * cos).max(min.y).min(max.y);
+                self.goal_pos.x = x - dist * sin;
+                self.goal_pos.z = z - dist * cos;
+            }
+        }
     }
 
     impl Default for RTS {
         fn default() -> Self {
+            let (x_rad, y_rad) = (Self::PI * 0.25, -Self::PI * 0.25);
+            let pos: Vec3 = (0., Self::height(y_rad), 0.).into();
             Self {
-                pos: (0., 0., 0.).into(),
-                x_rad: Self::PI * 0.25,
-                y_rad: -Self::PI * 0.25,
+                pos: pos.clone(),
+                x_rad,
+                y_rad,
+
+                goal_pos: pos,
+                goal_x_rad: x_rad,
+                goal_y_rad: y_rad,
+
                 radius: 1000.0,
                 speed_multiplier: 50.,
+                bounds: None,
             }
         }
     }
 
     impl Updater for RTS {
         fn update(&mut self, delta: &ResrcDeltaTime, updates: &Updates) -> (Vec3, Point3) {
-            let delta = delta.0 * self.speed_multiplier;
-            if updates.mov_left {
-                self.pos.x += delta * self.x_rad.cos();
-                self.pos.z -= delta * self.x_rad.sin();
-            }
-            if updates.mov_right {
-                self.pos.x -= delta * self.x_rad.cos();
-                self.pos.z += delta * self.x_rad.sin();
-            }
-            if updates.mov_up {
-                self.pos.x += delta * self.x_rad.sin();
-                self.pos.z += delta * self.x_rad.cos();
-            }
-            if updates.mov_down {
-                self.pos.x -= delta * self.x_rad.sin();
-                self.pos.z -= delta * self.x_rad.cos();
+            let dt = delta.0;
+            let delta = dt * self.speed_multiplier;
+
+            let screen = updates.screen_size;
+            let (at_left, at_up, at_right, at_down) =
+                if updates.cursor_inside && screen.x > 0. && screen.y > 0. {
+                    let pos = updates.mouse_pos;
+                    (
+                        pos.x <= Self::EDGE_MARGIN,
+                        pos.y <= Self::EDGE_MARGIN,
+                        pos.x >= screen.x - Self::EDGE_MARGIN,
+                        pos.y >= screen.y - Self::EDGE_MARGIN,
+                    )
+                } else {
+                    (false, false, false, false)
+                };
+
+            // Dragging the mouse drags the ground along with it.
+            let left = ((updates.mov_left || at_left) as i32
+                - (updates.mov_right || at_right) as i32) as f32
+                * delta
+                + updates.pan.x * Self::PAN_SPEED;
+            let forward = ((updates.mov_up || at_up) as i32 - (updates.mov_down || at_down) as i32)
+                as f32
+                * delta
+                + updates.pan.y * Self::PAN_SPEED;
+
+            let (sin, cos) = self.goal_x_rad.sin_cos();
+            self.goal_pos.x += left * cos + forward * sin;
+            self.goal_pos.z += -left * sin + forward * cos;
+
+            // Rotate around whatever lies at the center of the screen.
+            let rotation = (updates.rot_left as i32 - updates.rot_right as i32) as f32
+                * Self::ROTATION_SPEED
+                * dt;
+            if rotation != 0. {
+                let dist = self.goal_ground_distance();
+                let (sin, cos) = self.goal_x_rad.sin_cos();
+                let pivot = (self.goal_pos.x + dist * sin, self.goal_pos.z + dist * cos);
+                self.goal_x_rad += rotation;
+                let (sin, cos) = self.goal_x_rad.sin_cos();
+                self.goal_pos.x = pivot.0 - dist * sin;
+                self.goal_pos.z = pivot.1 - dist * cos;
             }
 
-            self.y_rad += updates.zoom as f32 * delta * 10. * Self::PI / 180.0;
+            self.goal_y_rad += updates.zoom as f32 * delta * 10. * Self::PI / 180.0;
             // self.y_rad = self.y_rad.max(-Self::PI / 3.0).min(-Self::PI * 0.15);
-            self.y_rad = self.y_rad.max(-Self::PI / 3.0).min(Self::PI / 3.);
-            self.pos.y = Self::Y * 2. * self.y_rad.abs();
+            self.goal_y_rad = self.goal_y_rad.max(-Self::PI / 3.0).min(Self::PI / 3.);
+
+            self.clamp_goal();
+
+            // Framerate-independent exponential smoothing.
+            let k = 1. - (-Self::SMOOTHING * dt).exp();
+            self.pos.x += (self.goal_pos.x - self.pos.x) * k;
+            self.pos.z += (self.goal_pos.z - self.pos.z) * k;
+            self.x_rad += (self.goal_x_rad - self.x_rad) * k;
+            self.y_rad += (self.goal_y_rad - self.y_rad) * k;
+            self.pos.y = Self::height(self.y_rad);
 
             // https://gamedev.stackexchange.com/a/159314
             let target = (
@@ -200,6 +392,31 @@ mod updaters {
 
             (self.pos.clone(), target.into())
         }
+
+        fn focus(&mut self, target: &Point3) {
+            // Only the ground position moves: height & angles are left as is.
+            let dir = (
+                self.goal_x_rad.sin() * self.goal_y_rad.cos(),
+                self.goal_y_rad.sin(),
+                self.goal_x_rad.cos() * self.goal_y_rad.cos(),
+            );
+            // Back off along the view direction until the target's height is
+            // reached, if the camera looks down at all.
+            let t = if dir.1 < 0. {
+                (target.y - Self::height(self.goal_y_rad)) / dir.1
+            } else {
+                0.
+            };
+            self.goal_pos.x = target.x - t * dir.0;
+            self.goal_pos.z = target.z - t * dir.2;
+
+            self.clamp_goal();
+        }
+
+        fn set_bounds(&mut self, min: Vec2, max: Vec2) {
+            self.bounds = Some((min, max));
+            self.clamp_goal();
+        }
     }
 
     // -----------------------------------------------------------------------------
@@ -306,3 +523,182 @@ mod updaters {
         }
     }
 }
+
+// -----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCREEN: (f32, f32) = (800., 600.);
+
+    fn camera(position: (f32, f32, f32), target: (f32, f32, f32)) -> Camera {
+        let inner = Camera3D::perspective(position.into(), target.into(), (0., 1., 0.).into(), 60.);
+        Camera::new(inner, Mode::RTS)
+    }
+
+    fn idle() -> Updates {
+        Updates {
+            mov_left: false,
+            mov_up: false,
+            mov_right: false,
+            mov_down: false,
+
+            rot_left: false,
+            rot_up: false,
+            rot_right: false,
+            rot_down: false,
+
+            mouse_pos: (400., 300.).into(),
+            cursor_inside: true,
+            mouse_diff: (0., 0.).into(),
+            pan: (0., 0.).into(),
+
+            screen_size: SCREEN.into(),
+
+            zoom: 0,
+        }
+    }
+
+    /// Where the camera's line of sight meets the ground.
+    fn ground((pos, target): (Vec3, Point3)) -> (f32, f32) {
+        let t = pos.y / (pos.y - target.y);
+        (
+            pos.x + (target.x - pos.x) * t,
+            pos.z + (target.z - pos.z) * t,
+        )
+    }
+
+    fn run(rts: &mut updaters::RTS, dt: f32, frames: usize, updates: &Updates) -> (Vec3, Point3) {
+        let delta = ResrcDeltaTime(dt);
+        (0..frames)
+            .map(|_| rts.update(&delta, updates))
+            .last()
+            .unwrap()
+    }
+
+    #[test]
+    fn rts_clamps_to_bounds() {
+        let mut rts = updaters::RTS::default();
+        rts.set_bounds((-10., -20.).into(), (10., 20.).into());
+        let within = |(x, z): (f32, f32)| {
+            let eps = 1e-2;
+            x >= -10. - eps && x <= 10. + eps && z >= -20. - eps && z <= 20. + eps
+        };
+
+        let mut updates = idle();
+        updates.mov_left = true;
+        updates.mov_up = true;
+        let (x, z) = ground(run(&mut rts, 1. / 60., 600, &updates));
+        assert!(within((x, z)), "({}, {}) is out of bounds", x, z);
+        // Pushed into the corner.
+        assert!((x - 10.).abs() < 1e-2 && (z - 20.).abs() < 1e-2);
+
+        // Rotating around a point on the border keeps it there, once settled:
+        // the goal is clamped, the easing towards it cuts corners.
+        updates.rot_left = true;
+        run(&mut rts, 1. / 60., 60, &updates);
+        let p = ground(run(&mut rts, 1. / 60., 120, &idle()));
+        assert!(within(p), "{:?} is out of bounds", p);
+
+        // Edge scrolling too.
+        let mut updates = idle();
+        updates.mouse_pos = (0., 300.).into();
+        let p = ground(run(&mut rts, 1. / 60., 600, &updates));
+        assert!(within(p), "{:?} is out of bounds", p);
+    }
+
+    #[test]
+    fn rts_smoothing_converges() {
+        let mut updates = idle();
+        updates.mov_up = true;
+        // Moves forward for half a second, then lets the camera settle while
+        // checking that it slows down every frame.
+        let settle = |fps: usize| {
+            let (mut rts, dt) = (updaters::RTS::default(), 1. / fps as f32);
+            run(&mut rts, dt, fps / 2, &updates);
+            let mut last = run(&mut rts, dt, 1, &idle()).0;
+            let mut step = std::f32::INFINITY;
+            for _ in 0..fps * 2 {
+                let pos = run(&mut rts, dt, 1, &idle()).0;
+                let next = (*pos - *last).magnitude();
+                assert!(next <= step, "{} > {}", next, step);
+                step = next;
+                last = pos;
+            }
+            assert!(step < 1e-3, "still moving by {}", step);
+
+            last
+        };
+
+        let start = run(&mut updaters::RTS::default(), 0., 1, &idle()).0;
+        let slow = settle(30);
+        assert!((*slow - *start).magnitude() > 20.);
+        // Framerate-independent, give or take the sampling of the ease.
+        let fast = settle(120);
+        assert!(
+            (*slow - *fast).magnitude() < 1e-2,
+            "{:?} != {:?}",
+            slow,
+            fast
+        );
+    }
+
+    #[test]
+    fn rts_edge_scrolls_only_from_inside() {
+        let still = run(&mut updaters::RTS::default(), 1. / 60., 60, &idle()).0;
+
+        // Where a cursor that never entered the window gets reported.
+        let mut updates = idle();
+        updates.mouse_pos = (0., 0.).into();
+        updates.cursor_inside = false;
+        let pos = run(&mut updaters::RTS::default(), 1. / 60., 60, &updates).0;
+        assert!((*pos - *still).magnitude() < 1e-6);
+
+        updates.cursor_inside = true;
+        let pos = run(&mut updaters::RTS::default(), 1. / 60., 60, &updates).0;
+        assert!((*pos - *still).magnitude() > 1.);
+    }
+
+    #[test]
+    fn world_to_screen_to_ray() {
+        let cam = camera((10., 20., 30.), (0., 0., 0.));
+
+        let center = cam
+            .world_to_screen((0., 0., 0.).into(), SCREEN.into())
+            .unwrap();
+        assert!((center.x - 400.).abs() < 1e-3 && (center.y - 300.).abs() < 1e-3);
+
+        for &p in &[(0., 0., 0.), (3., -2., 5.), (-8., 4., -1.), (12., 0., 25.)] {
+            let p = CGPoint3::new(p.0, p.1, p.2);
+            // On screen or not, as long as it's in front.
+            let pixel = cam.world_to_screen(p.into(), SCREEN.into()).unwrap();
+            let r = cam.screen_to_ray(pixel, SCREEN.into()).unwrap();
+            let to_p = p - r.origin;
+            // Off the ray by less than a thousandth of a unit, per unit of
+            // distance.
+            let off = to_p.cross(r.direction).magnitude() / to_p.magnitude();
+            assert!(off < 1e-3, "{:?} is {} away from {:?}", p, off, r);
+            assert!(to_p.dot(r.direction) > 0.);
+        }
+    }
+
+    #[test]
+    fn world_to_screen_behind() {
+        let cam = camera((10., 20., 30.), (0., 0., 0.));
+        assert!(cam
+            .world_to_screen((20., 40., 60.).into(), SCREEN.into())
+            .is_none());
+        assert!(cam
+            .world_to_screen((10., 20., 31.).into(), SCREEN.into())
+            .is_none());
+    }
+
+    #[test]
+    fn screen_to_ray_degenerate() {
+        let cam = camera((10., 20., 30.), (10., 20., 30.));
+        assert!(cam
+            .screen_to_ray((400., 300.).into(), SCREEN.into())
+            .is_none());
+    }
+}
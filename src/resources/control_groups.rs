@@ -0,0 +1,110 @@
+use specs::prelude::*;
+use std::collections::HashSet;
+
+// -----------------------------------------------------------------------------
+
+/// Selections stored under digits 1 to 9.
+#[derive(Debug, Default)]
+pub struct ControlGroups {
+    groups: [HashSet<Entity>; Self::NB_GROUPS],
+    last_recall: Option<(usize, f32)>,
+}
+
+impl ControlGroups {
+    /// Recalling the same group twice within this delay, in seconds, is a
+    /// double-tap.
+    pub const DOUBLE_TAP: f32 = 0.3;
+    pub const NB_GROUPS: usize = 9;
+
+    /// Returns the entities of `group`, which must be within `1..=9`.
+    pub fn get(&self, group: usize) -> &HashSet<Entity> {
+        &self.groups[group - 1]
+    }
+
+    /// Replaces the content of `group` with `entities`.
+    pub fn assign(&mut self, group: usize, entities: impl IntoIterator<Item = Entity>) {
+        let g = &mut self.groups[group - 1];
+        g.clear();
+        g.extend(entities);
+    }
+
+    /// Marks `group` as recalled at `now`, in seconds; returns true if this
+    /// makes for a double-tap.
+    pub fn recall(&mut self, group: usize, now: f32) -> bool {
+        let double_tap = match self.last_recall {
+            Some((last, at)) => last == group && now - at <= Self::DOUBLE_TAP,
+            None => false,
+        };
+        // A third tap starts over rather than chaining double-taps.
+        self.last_recall = if double_tap { None } else { Some((group, now)) };
+
+        double_tap
+    }
+
+    /// Drops the entities that aren't alive anymore from every group.
+    pub fn prune(&mut self, entities: &Entities) {
+        for g in &mut self.groups {
+            g.retain(|&e| entities.is_alive(e));
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TAP: f32 = ControlGroups::DOUBLE_TAP;
+
+    #[test]
+    fn recall_double_taps() {
+        let mut groups = ControlGroups::default();
+
+        assert!(!groups.recall(1, 10.));
+        assert!(groups.recall(1, 10. + TAP * 0.5));
+        // A third tap starts over...
+        assert!(!groups.recall(1, 10. + TAP * 0.75));
+        // ...and can itself be double-tapped.
+        assert!(groups.recall(1, 10. + TAP));
+
+        // Too slow.
+        assert!(!groups.recall(2, 20.));
+        assert!(!groups.recall(2, 20. + TAP * 1.5));
+        assert!(groups.recall(2, 20. + TAP * 2.));
+
+        // Switching groups in between.
+        assert!(!groups.recall(3, 30.));
+        assert!(!groups.recall(4, 30. + TAP * 0.25));
+        assert!(!groups.recall(3, 30. + TAP * 0.5));
+        assert!(groups.recall(3, 30. + TAP * 0.75));
+    }
+
+    #[test]
+    fn assign_and_prune() {
+        let mut world = World::new();
+        let entities: Vec<_> = (0..4).map(|_| world.create_entity().build()).collect();
+
+        let mut groups = ControlGroups::default();
+        groups.assign(1, entities[..3].iter().copied());
+        groups.assign(9, entities[2..].iter().copied());
+        assert_eq!(3, groups.get(1).len());
+        // Assigning replaces.
+        groups.assign(1, entities[..2].iter().copied());
+        assert_eq!(
+            entities[..2].iter().copied().collect::<HashSet<_>>(),
+            *groups.get(1)
+        );
+
+        world.delete_entity(entities[1]).unwrap();
+        world.delete_entity(entities[3]).unwrap();
+        world.maintain();
+        groups.prune(&world.entities());
+
+        assert_eq!(Some(&entities[0]), groups.get(1).iter().next());
+        assert_eq!(1, groups.get(1).len());
+        assert_eq!(Some(&entities[2]), groups.get(9).iter().next());
+        assert_eq!(1, groups.get(9).len());
+        assert!(groups.get(5).is_empty());
+    }
+}
@@ -0,0 +1,89 @@
+use crate::{
+    maths::Vec2i,
+    resources::{keyboard::KeyboardState, mouse::MouseState},
+};
+
+// -----------------------------------------------------------------------------
+
+/// Plain snapshot of the inputs of the current frame.
+///
+/// Filled by a single backend-specific system, everything else reads from
+/// here: this is what makes input-driven logic testable without a window.
+pub struct InputState {
+    keyboard: KeyboardState,
+    mouse: MouseState,
+    screen_size: Vec2i,
+    cursor_inside: bool,
+    time: f32,
+}
+
+impl Default for InputState {
+    fn default() -> Self {
+        Self {
+            keyboard: Default::default(),
+            mouse: Default::default(),
+            screen_size: (0, 0).into(),
+            cursor_inside: false,
+            time: 0.0,
+        }
+    }
+}
+
+impl InputState {
+    pub fn keyboard(&self) -> &KeyboardState {
+        &self.keyboard
+    }
+
+    pub fn keyboard_mut(&mut self) -> &mut KeyboardState {
+        &mut self.keyboard
+    }
+
+    pub fn mouse(&self) -> &MouseState {
+        &self.mouse
+    }
+
+    pub fn mouse_mut(&mut self) -> &mut MouseState {
+        &mut self.mouse
+    }
+
+    /// Size of the screen, in pixels.
+    pub fn screen_size(&self) -> Vec2i {
+        self.screen_size
+    }
+
+    pub fn set_screen_size(&mut self, size: Vec2i) {
+        self.screen_size = size;
+    }
+
+    /// True while the cursor is inside of the window, and the window is
+    /// focused.
+    ///
+    /// The mouse position is stale otherwise: it is (0, 0) until the cursor
+    /// first enters the window, and sticks to wherever it left it.
+    pub fn is_cursor_inside(&self) -> bool {
+        self.cursor_inside
+    }
+
+    pub fn set_cursor_inside(&mut self, inside: bool) {
+        self.cursor_inside = inside;
+    }
+
+    /// Time of the current frame, in seconds since the first one.
+    ///
+    /// Game time rather than wall-clock time, so that replays see the very
+    /// same timings as the original session.
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    /// Derives higher-level state (clicks, drags...) from the raw inputs.
+    ///
+    /// Must be called once per frame, after everything else has been set.
+    pub fn track(&mut self) {
+        self.mouse.track(self.time);
+    }
+}
@@ -0,0 +1,136 @@
+use raylib::consts::KeyboardKey;
+
+// -----------------------------------------------------------------------------
+
+pub struct KeyboardState {
+    pressed: [bool; Self::NB_KEYS],
+    released: [bool; Self::NB_KEYS],
+    down: [bool; Self::NB_KEYS],
+}
+
+impl Default for KeyboardState {
+    fn default() -> Self {
+        Self {
+            pressed: [false; Self::NB_KEYS],
+            released: [false; Self::NB_KEYS],
+            down: [false; Self::NB_KEYS],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+impl KeyboardState {
+    /// Every key that can be referred to by name, e.g. in key bindings.
+    pub const KEYS: &'static [(&'static str, KeyboardKey)] = {
+        use KeyboardKey::*;
+        &[
+            ("A", KEY_A),
+            ("B", KEY_B),
+            ("C", KEY_C),
+            ("D", KEY_D),
+            ("E", KEY_E),
+            ("F", KEY_F),
+            ("G", KEY_G),
+            ("H", KEY_H),
+            ("I", KEY_I),
+            ("J", KEY_J),
+            ("K", KEY_K),
+            ("L", KEY_L),
+            ("M", KEY_M),
+            ("N", KEY_N),
+            ("O", KEY_O),
+            ("P", KEY_P),
+            ("Q", KEY_Q),
+            ("R", KEY_R),
+            ("S", KEY_S),
+            ("T", KEY_T),
+            ("U", KEY_U),
+            ("V", KEY_V),
+            ("W", KEY_W),
+            ("X", KEY_X),
+            ("Y", KEY_Y),
+            ("Z", KEY_Z),
+            ("0", KEY_ZERO),
+            ("1", KEY_ONE),
+            ("2", KEY_TWO),
+            ("3", KEY_THREE),
+            ("4", KEY_FOUR),
+            ("5", KEY_FIVE),
+            ("6", KEY_SIX),
+            ("7", KEY_SEVEN),
+            ("8", KEY_EIGHT),
+            ("9", KEY_NINE),
+            ("F1", KEY_F1),
+            ("F2", KEY_F2),
+            ("F3", KEY_F3),
+            ("F4", KEY_F4),
+            ("F5", KEY_F5),
+            ("F6", KEY_F6),
+            ("F7", KEY_F7),
+            ("F8", KEY_F8),
+            ("F9", KEY_F9),
+            ("F10", KEY_F10),
+            ("F11", KEY_F11),
+            ("F12", KEY_F12),
+            ("LeftShift", KEY_LEFT_SHIFT),
+            ("RightShift", KEY_RIGHT_SHIFT),
+            ("LeftControl", KEY_LEFT_CONTROL),
+            ("RightControl", KEY_RIGHT_CONTROL),
+            ("LeftAlt", KEY_LEFT_ALT),
+            ("RightAlt", KEY_RIGHT_ALT),
+            ("Space", KEY_SPACE),
+            ("Escape", KEY_ESCAPE),
+            ("Enter", KEY_ENTER),
+            ("Tab", KEY_TAB),
+            ("Backspace", KEY_BACKSPACE),
+            ("Delete", KEY_DELETE),
+            ("Left", KEY_LEFT),
+            ("Right", KEY_RIGHT),
+            ("Up", KEY_UP),
+            ("Down", KEY_DOWN),
+        ]
+    };
+    // Raylib's key codes all fit within GLFW's range.
+    const NB_KEYS: usize = 512;
+
+    pub fn key_from_name(name: &str) -> Option<KeyboardKey> {
+        Self::KEYS.iter().find(|(n, _)| *n == name).map(|&(_, k)| k)
+    }
+
+    pub fn key_name(key: KeyboardKey) -> Option<&'static str> {
+        Self::KEYS.iter().find(|(_, k)| *k == key).map(|&(n, _)| n)
+    }
+
+    pub fn is_pressed(&self, key: KeyboardKey) -> bool {
+        self.pressed[key as usize]
+    }
+
+    pub fn is_released(&self, key: KeyboardKey) -> bool {
+        self.released[key as usize]
+    }
+
+    pub fn is_down(&self, key: KeyboardKey) -> bool {
+        self.down[key as usize]
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        use KeyboardKey::*;
+        Modifiers {
+            shift: self.is_down(KEY_LEFT_SHIFT) || self.is_down(KEY_RIGHT_SHIFT),
+            ctrl: self.is_down(KEY_LEFT_CONTROL) || self.is_down(KEY_RIGHT_CONTROL),
+            alt: self.is_down(KEY_LEFT_ALT) || self.is_down(KEY_RIGHT_ALT),
+        }
+    }
+
+    pub fn set(&mut self, key: KeyboardKey, pressed: bool, released: bool, down: bool) {
+        self.pressed[key as usize] = pressed;
+        self.released[key as usize] = released;
+        self.down[key as usize] = down;
+    }
+}
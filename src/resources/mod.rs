@@ -1,16 +1,28 @@
+mod bindings;
 mod bounding_tree;
 mod camera;
+mod control_groups;
+mod inputs;
+mod keyboard;
 mod models;
 mod mouse;
 mod raylib;
+mod recording;
+mod selection;
 
 pub mod prelude {
     pub use super::{
+        bindings::{Action, Bindings as ResrcBindings, Chord, Input},
         bounding_tree::BoundingTree as ResrcBoundingTree,
         camera::{Camera as ResrcCamera, Mode as ResrcCameraMode},
+        control_groups::ControlGroups as ResrcControlGroups,
+        inputs::InputState as ResrcInputState,
+        keyboard::{KeyboardState, Modifiers},
         models::{MeshID, MeshStore as ResrcMeshStore, Model},
-        mouse::MouseState as ResrcMouseState,
+        mouse::MouseState,
         raylib::Raylib as ResrcRaylib,
+        recording::Recording as ResrcRecording,
+        selection::{SelectionEvent, SelectionEvents as ResrcSelectionEvents},
         DeltaTime as ResrcDeltaTime, ModelView as ResrcModelView, Projection as ResrcProjection,
     };
 }
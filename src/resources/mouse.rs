@@ -1,14 +1,26 @@
-use crate::{components::prelude::*, maths::Vec2i, resources::prelude::*};
+use crate::maths::Vec2i;
 
 // -----------------------------------------------------------------------------
 
 // TODO(cmc): bitsets
-// TODO(cmc): portable design (i.e. abstract raylib)
 pub struct MouseState {
     pos: Vec2i,
-    pressed: [bool; 8],
-    released: [bool; 8],
-    down: [bool; 8],
+    pressed: [bool; Self::NB_BUTTONS],
+    released: [bool; Self::NB_BUTTONS],
+    down: [bool; Self::NB_BUTTONS],
+    wheel: i32,
+
+    // Derived from the raw state above by `track`.
+    time: f32,
+    drag_threshold: i32,
+    /// Time & position of the last press of each button.
+    pressed_at: [Option<(f32, Vec2i)>; Self::NB_BUTTONS],
+    /// Time & position of the last click that could start a double-click.
+    clicked_at: [Option<(f32, Vec2i)>; Self::NB_BUTTONS],
+    dragging: [bool; Self::NB_BUTTONS],
+    /// Whether the last press of each button completed a double-click.
+    double_press: [bool; Self::NB_BUTTONS],
+    wheel_total: i32,
 }
 
 impl Default for MouseState {
@@ -18,11 +30,26 @@ impl Default for MouseState {
             pressed: Default::default(),
             released: Default::default(),
             down: Default::default(),
+            wheel: 0,
+
+            time: 0.0,
+            drag_threshold: Self::DRAG_THRESHOLD,
+            pressed_at: Default::default(),
+            clicked_at: Default::default(),
+            dragging: Default::default(),
+            double_press: Default::default(),
+            wheel_total: 0,
         }
     }
 }
 
 impl MouseState {
+    /// Two clicks within this delay, in seconds, make for a double-click.
+    pub const DOUBLE_CLICK: f32 = 0.3;
+    /// Default distance, in pixels, a held button has to move for a drag.
+    pub const DRAG_THRESHOLD: i32 = 4;
+    const NB_BUTTONS: usize = 8;
+
     pub fn position(&self) -> Vec2i {
         self.pos
     }
@@ -39,23 +66,255 @@ impl MouseState {
         self.down[button]
     }
 
-    pub fn update(&mut self, rl: &ResrcRaylib) {
-        use ::raylib::prelude::*;
-        use MouseButton::*;
-        rl.read(|rl| {
-            self.pos = (rl.get_mouse_x(), rl.get_mouse_y()).into();
+    /// Wheel movement since last frame.
+    pub fn wheel(&self) -> i32 {
+        self.wheel
+    }
+
+    /// Wheel movement since the first frame.
+    pub fn wheel_total(&self) -> i32 {
+        self.wheel_total
+    }
+
+    pub fn set_position(&mut self, pos: Vec2i) {
+        self.pos = pos;
+    }
+
+    pub fn set_button(&mut self, button: usize, pressed: bool, released: bool, down: bool) {
+        self.pressed[button] = pressed;
+        self.released[button] = released;
+        self.down[button] = down;
+    }
+
+    pub fn set_wheel(&mut self, wheel: i32) {
+        self.wheel = wheel;
+    }
+}
+
+impl MouseState {
+    pub fn drag_threshold(&self) -> i32 {
+        self.drag_threshold
+    }
+
+    pub fn set_drag_threshold(&mut self, threshold: i32) {
+        self.drag_threshold = threshold;
+    }
 
-            self.pressed[0] = rl.is_mouse_button_pressed(MOUSE_LEFT_BUTTON);
-            self.pressed[1] = rl.is_mouse_button_pressed(MOUSE_RIGHT_BUTTON);
-            self.pressed[2] = rl.is_mouse_button_pressed(MOUSE_MIDDLE_BUTTON);
+    /// Where `button` went down, while it's held and on the frame it's
+    /// released.
+    pub fn drag_start(&self, button: usize) -> Option<Vec2i> {
+        if self.down[button] || self.released[button] {
+            self.pressed_at[button].map(|(_, pos)| pos)
+        } else {
+            None
+        }
+    }
 
-            self.released[0] = rl.is_mouse_button_released(MOUSE_LEFT_BUTTON);
-            self.released[1] = rl.is_mouse_button_released(MOUSE_RIGHT_BUTTON);
-            self.released[2] = rl.is_mouse_button_released(MOUSE_MIDDLE_BUTTON);
+    /// True once `button` has moved further than the drag threshold since it
+    /// went down, until it's pressed again.
+    pub fn is_dragging(&self, button: usize) -> bool {
+        self.dragging[button]
+    }
 
-            self.down[0] = rl.is_mouse_button_down(MOUSE_LEFT_BUTTON);
-            self.down[1] = rl.is_mouse_button_down(MOUSE_RIGHT_BUTTON);
-            self.down[2] = rl.is_mouse_button_down(MOUSE_MIDDLE_BUTTON);
-        });
+    /// True on the frame `button` gets released without having been dragged.
+    pub fn is_clicked(&self, button: usize) -> bool {
+        self.released[button] && !self.dragging[button]
+    }
+
+    /// True on the frame `button` gets pressed for the second time of a
+    /// double-click.
+    pub fn is_double_clicked(&self, button: usize) -> bool {
+        self.pressed[button] && self.double_press[button]
+    }
+
+    /// How long `button` has been held for, in seconds.
+    pub fn held_for(&self, button: usize) -> Option<f32> {
+        if self.down[button] {
+            self.pressed_at[button].map(|(at, _)| self.time - at)
+        } else {
+            None
+        }
+    }
+
+    /// Derives clicks, drags & co from the raw state of the frame at `time`.
+    ///
+    /// Must be called once per frame, after every raw input has been set.
+    pub fn track(&mut self, time: f32) {
+        self.time = time;
+        self.wheel_total += self.wheel;
+
+        let pos = self.pos;
+        let threshold = self.drag_threshold;
+        let moved =
+            |from: Vec2i| (pos.x - from.x).abs() > threshold || (pos.y - from.y).abs() > threshold;
+
+        for b in 0..Self::NB_BUTTONS {
+            if self.pressed[b] {
+                self.pressed_at[b] = Some((time, pos));
+                self.dragging[b] = false;
+                self.double_press[b] = self.clicked_at[b].map_or(false, |(at, from)| {
+                    time - at <= Self::DOUBLE_CLICK && !moved(from)
+                });
+            }
+
+            if self.down[b] || self.released[b] {
+                if let Some((_, from)) = self.pressed_at[b] {
+                    self.dragging[b] |= moved(from);
+                }
+            }
+
+            if self.released[b] {
+                // A double-click's second click doesn't start another one,
+                // and neither do drags.
+                self.clicked_at[b] = if self.dragging[b] || self.double_press[b] {
+                    None
+                } else {
+                    self.pressed_at[b]
+                };
+            }
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEFT: usize = 0;
+
+    /// Replays one frame worth of left button state at `time`.
+    fn frame(mouse: &mut MouseState, time: f32, pos: (i32, i32), pressed: bool, released: bool) {
+        let down = pressed || (mouse.is_down(LEFT) && !released);
+        mouse.set_position(pos.into());
+        mouse.set_button(LEFT, pressed, released, down);
+        mouse.set_wheel(0);
+        mouse.track(time);
+    }
+
+    fn click_at(mouse: &mut MouseState, time: f32, pos: (i32, i32)) {
+        frame(mouse, time, pos, true, false);
+        frame(mouse, time + 0.05, pos, false, true);
+    }
+
+    fn drag_start(mouse: &MouseState) -> Option<(i32, i32)> {
+        mouse.drag_start(LEFT).map(|p| (p.x, p.y))
+    }
+
+    #[test]
+    fn click() {
+        let mut mouse = MouseState::default();
+        frame(&mut mouse, 0., (100, 100), true, false);
+        assert!(!mouse.is_clicked(LEFT));
+        assert_eq!(Some((100, 100)), drag_start(&mouse));
+
+        frame(&mut mouse, 0.1, (101, 99), false, true);
+        assert!(mouse.is_clicked(LEFT));
+        assert!(!mouse.is_dragging(LEFT));
+        assert_eq!(Some((100, 100)), drag_start(&mouse));
+
+        frame(&mut mouse, 0.2, (101, 99), false, false);
+        assert!(!mouse.is_clicked(LEFT));
+        assert_eq!(None, drag_start(&mouse));
+    }
+
+    #[test]
+    fn drag_threshold() {
+        let t = MouseState::DRAG_THRESHOLD;
+        let mut mouse = MouseState::default();
+
+        // Just under: still a click.
+        frame(&mut mouse, 0., (100, 100), true, false);
+        frame(&mut mouse, 0.1, (100 + t, 100 - t), false, false);
+        assert!(!mouse.is_dragging(LEFT));
+        frame(&mut mouse, 0.2, (100 + t, 100 - t), false, true);
+        assert!(mouse.is_clicked(LEFT));
+
+        // Just over: a drag, even once back where it started.
+        frame(&mut mouse, 1., (100, 100), true, false);
+        frame(&mut mouse, 1.1, (100, 100 + t + 1), false, false);
+        assert!(mouse.is_dragging(LEFT));
+        frame(&mut mouse, 1.2, (100, 100), false, false);
+        assert!(mouse.is_dragging(LEFT));
+        frame(&mut mouse, 1.3, (100, 100), false, true);
+        assert!(!mouse.is_clicked(LEFT));
+
+        // Until the next press.
+        frame(&mut mouse, 2., (100, 100), true, false);
+        assert!(!mouse.is_dragging(LEFT));
+
+        // Thresholds are configurable.
+        mouse.set_drag_threshold(0);
+        frame(&mut mouse, 2.1, (101, 100), false, false);
+        assert!(mouse.is_dragging(LEFT));
+    }
+
+    #[test]
+    fn double_click() {
+        let mut mouse = MouseState::default();
+
+        click_at(&mut mouse, 0., (10, 10));
+        frame(&mut mouse, MouseState::DOUBLE_CLICK, (10, 10), true, false);
+        assert!(mouse.is_double_clicked(LEFT));
+        frame(&mut mouse, 0.35, (10, 10), false, true);
+        assert!(mouse.is_clicked(LEFT));
+        assert!(!mouse.is_double_clicked(LEFT));
+
+        // The third click starts over rather than chaining.
+        frame(&mut mouse, 0.4, (10, 10), true, false);
+        assert!(!mouse.is_double_clicked(LEFT));
+        frame(&mut mouse, 0.45, (10, 10), false, true);
+        frame(&mut mouse, 0.5, (10, 10), true, false);
+        assert!(mouse.is_double_clicked(LEFT));
+        frame(&mut mouse, 0.55, (10, 10), false, true);
+
+        // Just too late: the press went down DOUBLE_CLICK after the first one.
+        click_at(&mut mouse, 10., (10, 10));
+        frame(
+            &mut mouse,
+            10. + MouseState::DOUBLE_CLICK + 0.01,
+            (10, 10),
+            true,
+            false,
+        );
+        assert!(!mouse.is_double_clicked(LEFT));
+        frame(&mut mouse, 10.5, (10, 10), false, true);
+
+        // Too far away.
+        click_at(&mut mouse, 20., (10, 10));
+        frame(&mut mouse, 20.1, (30, 10), true, false);
+        assert!(!mouse.is_double_clicked(LEFT));
+        frame(&mut mouse, 20.15, (30, 10), false, true);
+
+        // Drags don't count as the first click.
+        frame(&mut mouse, 30., (10, 10), true, false);
+        frame(&mut mouse, 30.05, (50, 10), false, true);
+        frame(&mut mouse, 30.1, (50, 10), true, false);
+        assert!(!mouse.is_double_clicked(LEFT));
+    }
+
+    #[test]
+    fn held_for() {
+        let mut mouse = MouseState::default();
+        assert_eq!(None, mouse.held_for(LEFT));
+
+        frame(&mut mouse, 1., (0, 0), true, false);
+        assert_eq!(Some(0.), mouse.held_for(LEFT));
+        frame(&mut mouse, 1.5, (0, 0), false, false);
+        assert_eq!(Some(0.5), mouse.held_for(LEFT));
+        frame(&mut mouse, 2., (0, 0), false, true);
+        assert_eq!(None, mouse.held_for(LEFT));
+    }
+
+    #[test]
+    fn wheel_total() {
+        let mut mouse = MouseState::default();
+        for (time, &wheel) in [3, 0, -1, 2].iter().enumerate() {
+            mouse.set_wheel(wheel);
+            mouse.track(time as f32);
+            assert_eq!(wheel, mouse.wheel());
+        }
+        assert_eq!(4, mouse.wheel_total());
     }
 }
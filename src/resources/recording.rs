@@ -0,0 +1,109 @@
+use crate::resources::{bindings::Input, inputs::InputState};
+use anyhow::Result as AnyResult;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// -----------------------------------------------------------------------------
+
+/// Everything the dispatcher got to see from the outside world during a
+/// single frame.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Frame {
+    delta: f32,
+    screen_size: (i32, i32),
+    mouse_pos: (i32, i32),
+    /// Missing from older recordings, where it used to be implied.
+    #[serde(default = "Frame::cursor_inside_default")]
+    cursor_inside: bool,
+    wheel: i32,
+    /// Only the inputs that aren't idle: (input, pressed, released, down).
+    inputs: Vec<(Input, bool, bool, bool)>,
+}
+
+impl Frame {
+    fn cursor_inside_default() -> bool {
+        true
+    }
+}
+
+/// A session's inputs, frame by frame, so that it can be replayed exactly as
+/// it ran.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Recording {
+    frames: Vec<Frame>,
+}
+
+impl Recording {
+    pub fn from_json(json: &str) -> AnyResult<Self> {
+        serde_json::from_str(json).map_err(Into::into)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> AnyResult<Self> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn to_json(&self) -> String {
+        // Plain numbers & strings all the way down: cannot fail.
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn to_file(&self, path: impl AsRef<Path>) -> AnyResult<()> {
+        std::fs::write(path, self.to_json()).map_err(Into::into)
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Appends the state of `inputs` as the next frame, which lasted `delta`
+    /// seconds.
+    pub fn push(&mut self, inputs: &InputState, delta: f32) {
+        let screen = inputs.screen_size();
+        let mouse = inputs.mouse().position();
+        let active = Input::all()
+            .map(|i| {
+                (
+                    i,
+                    i.is_pressed(inputs),
+                    i.is_released(inputs),
+                    i.is_down(inputs),
+                )
+            })
+            .filter(|&(_, pressed, released, down)| pressed || released || down)
+            .collect();
+
+        self.frames.push(Frame {
+            delta,
+            screen_size: (screen.x, screen.y),
+            mouse_pos: (mouse.x, mouse.y),
+            cursor_inside: inputs.is_cursor_inside(),
+            wheel: inputs.mouse().wheel(),
+            inputs: active,
+        });
+    }
+
+    /// Overwrites `inputs` with the state of the `idx`-th frame and returns
+    /// how long that frame lasted, or `None` past the end of the recording.
+    pub fn replay(&self, idx: usize, inputs: &mut InputState) -> Option<f32> {
+        let frame = self.frames.get(idx)?;
+
+        for i in Input::all() {
+            i.set(inputs, false, false, false);
+        }
+        for &(i, pressed, released, down) in &frame.inputs {
+            i.set(inputs, pressed, released, down);
+        }
+        inputs.set_screen_size(frame.screen_size.into());
+        inputs.set_cursor_inside(frame.cursor_inside);
+        inputs.mouse_mut().set_position(frame.mouse_pos.into());
+        inputs.mouse_mut().set_wheel(frame.wheel);
+        inputs.set_time(inputs.time() + frame.delta);
+        inputs.track();
+
+        Some(frame.delta)
+    }
+}
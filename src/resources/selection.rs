@@ -0,0 +1,18 @@
+use specs::{prelude::*, shrev::EventChannel};
+
+// -----------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelectionEvent {
+    /// The selection was modified; neither list is ever empty at once.
+    SelectionChanged {
+        added: Vec<Entity>,
+        removed: Vec<Entity>,
+    },
+    /// The cursor started hovering over an entity.
+    HoverStarted(Entity),
+    /// The cursor stopped hovering over an entity, which might be dead by now.
+    HoverEnded(Entity),
+}
+
+pub type SelectionEvents = EventChannel<SelectionEvent>;
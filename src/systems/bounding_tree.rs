@@ -1,10 +1,14 @@
 use crate::{components::prelude::*, maths::prelude::*, resources::prelude::*};
 use specs::prelude::*;
+use std::collections::HashSet;
 
 // -----------------------------------------------------------------------------
 
 #[derive(Default)]
-pub struct BoundingTree;
+pub struct BoundingTree {
+    model_events: Option<ReaderId<ComponentEvent>>,
+    voxel_events: Option<ReaderId<ComponentEvent>>,
+}
 
 impl<'a> System<'a> for BoundingTree {
     type SystemData = (
@@ -12,10 +16,49 @@ impl<'a> System<'a> for BoundingTree {
         WriteExpect<'a, ResrcBoundingTree>,
         ReadStorage<'a, CompModel3D>,
         ReadStorage<'a, CompTransform3D>,
+        ReadStorage<'a, CompVoxelModel>,
+        ReadStorage<'a, CompGridPosition>,
         WriteStorage<'a, CompInvalidated>,
     );
 
-    fn run(&mut self, (entities, mut bt, models, transforms, mut invalidated): Self::SystemData) {
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.model_events = world
+            .write_storage::<CompModel3D>()
+            .register_reader()
+            .into();
+        self.voxel_events = world
+            .write_storage::<CompVoxelModel>()
+            .register_reader()
+            .into();
+    }
+
+    fn run(
+        &mut self,
+        (entities, mut bt, models, transforms, voxels, grid_pos, mut invalidated): Self::SystemData,
+    ) {
+        // Entity deletions show up as removed components.
+        let mut removed = HashSet::new();
+        let events = models
+            .channel()
+            .read(self.model_events.as_mut().unwrap())
+            .chain(voxels.channel().read(self.voxel_events.as_mut().unwrap()));
+        for event in events {
+            if let ComponentEvent::Removed(id) = event {
+                removed.insert(*id);
+            }
+        }
+        if !removed.is_empty() {
+            let dead: Vec<_> = bt
+                .entities()
+                .filter(|e| removed.contains(&e.id()))
+                .filter(|&e| !models.contains(e) && !voxels.contains(e))
+                .collect();
+            for e in dead {
+                bt.remove_entity(e);
+            }
+        }
+
         // TODO(cmc): can probably greatly simplify all of this with swizzling
         // and a better maths module.
         for (e, model, transform, _) in (&entities, &models, &transforms, &invalidated).join() {
@@ -33,9 +76,268 @@ impl<'a> System<'a> for BoundingTree {
             );
         }
 
+        for (e, voxel, pos, _) in (&entities, &voxels, &grid_pos, &invalidated).join() {
+            // Tight bounds around the occupied voxels, not the whole chunks.
+            // There's nothing left to select in an empty model.
+            let (min, max) = match voxel.0.bounds() {
+                Some(bounds) => bounds,
+                None => {
+                    bt.remove_entity(e);
+                    continue;
+                }
+            };
+            let pos = pos.0;
+            bt.update_entity(
+                e,
+                (
+                    (pos.x + min.x) as f32,
+                    (pos.y + min.y) as f32,
+                    (pos.z + min.z) as f32,
+                )
+                    .into(),
+                (
+                    (pos.x + max.x + 1) as f32,
+                    (pos.y + max.y + 1) as f32,
+                    (pos.z + max.z + 1) as f32,
+                )
+                    .into(),
+            );
+        }
+
         bt.refresh();
         for e in entities.join() {
             invalidated.remove(e);
         }
     }
 }
+
+// -----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::VoxelModel as RawVoxelModel;
+    use raylib::prelude::Camera3D;
+
+    fn dragon() -> RawVoxelModel {
+        let data = include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/submodules/voxel-model/vox/scan/dragon.vox"
+        ));
+        RawVoxelModel::from_vox(data).unwrap().remove(0)
+    }
+
+    fn setup() -> (World, BoundingTree) {
+        let mut world = World::new();
+        world.insert(ResrcBoundingTree::new());
+        let mut sys = BoundingTree::default();
+        System::setup(&mut sys, &mut world);
+
+        (world, sys)
+    }
+
+    fn spawn(world: &mut World, model: RawVoxelModel, pos: Vec3i) -> Entity {
+        world
+            .create_entity()
+            .with(CompVoxelModel(model))
+            .with(CompGridPosition(pos))
+            .with(CompInvalidated)
+            .build()
+    }
+
+    #[test]
+    fn spawned_dragon_is_selectable() {
+        let (mut world, mut sys) = setup();
+        let pos: Vec3i = (-40, 0, 25).into();
+        let (min, max) = dragon().bounds().unwrap();
+        let e = spawn(&mut world, dragon(), pos);
+
+        sys.run_now(&world);
+        world.maintain();
+
+        let bt = world.read_resource::<ResrcBoundingTree>();
+        let min = CGPoint3::new(
+            (pos.x + min.x) as f32,
+            (pos.y + min.y) as f32,
+            (pos.z + min.z) as f32,
+        );
+        let max = CGPoint3::new(
+            (pos.x + max.x + 1) as f32,
+            (pos.y + max.y + 1) as f32,
+            (pos.z + max.z + 1) as f32,
+        );
+        let (bt_min, bt_max) = bt.bounds(e).unwrap();
+        assert_eq!((min, max), (*bt_min, *bt_max));
+
+        let center = CGPoint3::new(
+            (min.x + max.x) / 2.,
+            (min.y + max.y) / 2.,
+            (min.z + max.z) / 2.,
+        );
+
+        let eye = center + CGVec3::new(0., 100., 0.);
+        let r = collision::Ray3::new(eye, CGVec3::new(0., -1., 0.));
+        assert_eq!(
+            vec![e],
+            bt.test_ray(&r)
+                .into_iter()
+                .map(|(e, _)| e)
+                .collect::<Vec<_>>()
+        );
+
+        let eye = center + CGVec3::new(50., 80., 50.);
+        let inner = Camera3D::perspective(
+            (eye.x, eye.y, eye.z).into(),
+            (center.x, center.y, center.z).into(),
+            (0., 1., 0.).into(),
+            60.,
+        );
+        let cam = ResrcCamera::new(inner, ResrcCameraMode::RTS);
+        let frustum = cam
+            .view_projection((800., 600.).into())
+            .to_frustum()
+            .unwrap();
+        assert_eq!(vec![e], bt.test_frustum(&frustum).collect::<Vec<_>>());
+
+        // Looking away.
+        let inner = Camera3D::perspective(
+            (eye.x, eye.y, eye.z).into(),
+            (eye.x * 2. - center.x, eye.y, eye.z * 2. - center.z).into(),
+            (0., 1., 0.).into(),
+            60.,
+        );
+        let cam = ResrcCamera::new(inner, ResrcCameraMode::RTS);
+        let frustum = cam
+            .view_projection((800., 600.).into())
+            .to_frustum()
+            .unwrap();
+        assert_eq!(0, bt.test_frustum(&frustum).count());
+
+        assert!(world.read_storage::<CompInvalidated>().get(e).is_none());
+    }
+
+    fn voxel() -> RawVoxelModel {
+        let mut model = RawVoxelModel::default();
+        model.set(&(0, 0, 0).into(), 1);
+
+        model
+    }
+
+    #[test]
+    fn emptied_models_are_removed() {
+        let (mut world, mut sys) = setup();
+        let e = spawn(&mut world, voxel(), (0, 0, 0).into());
+        let other = spawn(&mut world, voxel(), (4, 0, 0).into());
+        sys.run_now(&world);
+        world.maintain();
+        assert!(world
+            .read_resource::<ResrcBoundingTree>()
+            .bounds(e)
+            .is_some());
+
+        world
+            .write_storage::<CompVoxelModel>()
+            .get_mut(e)
+            .unwrap()
+            .0
+            .set(&(0, 0, 0).into(), 0);
+        world
+            .write_storage::<CompInvalidated>()
+            .insert(e, CompInvalidated)
+            .unwrap();
+        sys.run_now(&world);
+        world.maintain();
+
+        let bt = world.read_resource::<ResrcBoundingTree>();
+        assert!(bt.bounds(e).is_none());
+        assert_eq!(vec![other], bt.entities().collect::<Vec<_>>());
+        let r = collision::Ray3::new(CGPoint3::new(-10., 0.5, 0.5), CGVec3::new(1., 0., 0.));
+        assert_eq!(
+            vec![other],
+            bt.test_ray(&r)
+                .into_iter()
+                .map(|(e, _)| e)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn queries_skip_dead_entities() {
+        let (mut world, mut sys) = setup();
+        let entities: Vec<_> = (0..32)
+            .map(|i| spawn(&mut world, voxel(), (i * 4, 0, 0).into()))
+            .collect();
+        sys.run_now(&world);
+        world.maintain();
+
+        // Kill a bunch at once, and strip another one of its model only.
+        let dead: Vec<_> = entities.iter().copied().step_by(3).collect();
+        world.delete_entities(&dead).unwrap();
+        let stripped = entities[1];
+        world.write_storage::<CompVoxelModel>().remove(stripped);
+        world.maintain();
+        sys.run_now(&world);
+        world.maintain();
+
+        let alive: HashSet<_> = entities
+            .iter()
+            .copied()
+            .filter(|e| !dead.contains(e) && *e != stripped)
+            .collect();
+
+        let bt = world.read_resource::<ResrcBoundingTree>();
+        assert_eq!(alive, bt.entities().collect());
+        for e in dead.iter().chain(Some(&stripped)) {
+            assert!(bt.bounds(*e).is_none());
+        }
+        // Surviving leaves must still map to their own bounds.
+        for (i, e) in entities.iter().enumerate() {
+            if alive.contains(e) {
+                let x = i as f32 * 4.;
+                let (min, max) = bt.bounds(*e).unwrap();
+                assert_eq!(
+                    (CGPoint3::new(x, 0., 0.), CGPoint3::new(x + 1., 1., 1.)),
+                    (*min, *max)
+                );
+            }
+        }
+
+        let r = collision::Ray3::new(CGPoint3::new(-10., 0.5, 0.5), CGVec3::new(1., 0., 0.));
+        assert_eq!(alive, bt.test_ray(&r).into_iter().map(|(e, _)| e).collect());
+        assert_eq!(
+            alive,
+            bt.test_aabb(
+                (-1000., -1000., -1000.).into(),
+                (1000., 1000., 1000.).into()
+            )
+            .collect()
+        );
+        assert_eq!(
+            alive,
+            bt.test_sphere((64., 0., 0.).into(), 1000.)
+                .into_iter()
+                .map(|(e, _)| e)
+                .collect()
+        );
+        assert_eq!(
+            alive,
+            bt.nearest((64., 0., 0.).into(), entities.len(), |_| true)
+                .into_iter()
+                .map(|(e, _)| e)
+                .collect()
+        );
+
+        let inner = Camera3D::perspective(
+            (64., 300., 1.).into(),
+            (64., 0., 0.).into(),
+            (0., 1., 0.).into(),
+            60.,
+        );
+        let cam = ResrcCamera::new(inner, ResrcCameraMode::RTS);
+        let frustum = cam
+            .view_projection((800., 600.).into())
+            .to_frustum()
+            .unwrap();
+        assert_eq!(alive, bt.test_frustum(&frustum).collect());
+    }
+}
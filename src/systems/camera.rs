@@ -8,12 +8,13 @@ pub struct Camera;
 
 impl<'a> System<'a> for Camera {
     type SystemData = (
-        ReadExpect<'a, ResrcRaylib>,
+        ReadExpect<'a, ResrcInputState>,
+        ReadExpect<'a, ResrcBindings>,
         ReadExpect<'a, ResrcDeltaTime>,
         WriteExpect<'a, ResrcCamera>,
     );
 
-    fn run(&mut self, (rl, delta, mut cam): Self::SystemData) {
-        cam.update(&rl, &delta);
+    fn run(&mut self, (inputs, bindings, delta, mut cam): Self::SystemData) {
+        cam.update(&inputs, &bindings, &delta);
     }
 }
@@ -1,18 +1,295 @@
 use crate::resources::prelude::*;
+use raylib::consts::MouseButton;
 use specs::prelude::*;
 
 // -----------------------------------------------------------------------------
 
+/// Polls raylib for this frame's inputs.
+///
+/// This is the only system that knows where inputs come from: everybody else
+/// reads `ResrcInputState`.
 #[derive(Default)]
-pub struct Mouse;
+pub struct Inputs {
+    /// Whether the cursor ever moved since the window opened.
+    entered: bool,
+}
+
+impl Inputs {
+    /// Raylib buttons, indexed as in `MouseState`.
+    const BUTTONS: &'static [MouseButton] = {
+        use MouseButton::*;
+        &[MOUSE_LEFT_BUTTON, MOUSE_RIGHT_BUTTON, MOUSE_MIDDLE_BUTTON]
+    };
+}
 
-impl<'a> System<'a> for Mouse {
+impl<'a> System<'a> for Inputs {
     type SystemData = (
         ReadExpect<'a, ResrcRaylib>,
-        WriteExpect<'a, ResrcMouseState>,
+        ReadExpect<'a, ResrcDeltaTime>,
+        WriteExpect<'a, ResrcInputState>,
+    );
+
+    fn run(&mut self, (rl, delta, mut inputs): Self::SystemData) {
+        let time = inputs.time() + delta.0;
+        inputs.set_time(time);
+
+        let entered = &mut self.entered;
+        rl.read(|rl| {
+            let size = (rl.get_screen_width(), rl.get_screen_height());
+            inputs.set_screen_size(size.into());
+
+            // TODO(cmc): raylib 3 has `IsCursorOnScreen` & `IsWindowFocused`.
+            // Until then, a cursor that never moved hasn't entered the window
+            // yet.
+            let pos = (rl.get_mouse_x(), rl.get_mouse_y());
+            let prev = inputs.mouse().position();
+            *entered |= pos != (prev.x, prev.y);
+            let on_screen = pos.0 >= 0 && pos.1 >= 0 && pos.0 < size.0 && pos.1 < size.1;
+            inputs.set_cursor_inside(*entered && on_screen && !rl.is_window_minimized());
+
+            let kbd = inputs.keyboard_mut();
+            for &(_, key) in KeyboardState::KEYS {
+                kbd.set(
+                    key,
+                    rl.is_key_pressed(key),
+                    rl.is_key_released(key),
+                    rl.is_key_down(key),
+                );
+            }
+
+            let mouse = inputs.mouse_mut();
+            mouse.set_position(pos.into());
+            for (i, &button) in Self::BUTTONS.iter().enumerate() {
+                mouse.set_button(
+                    i,
+                    rl.is_mouse_button_pressed(button),
+                    rl.is_mouse_button_released(button),
+                    rl.is_mouse_button_down(button),
+                );
+            }
+            mouse.set_wheel(rl.get_mouse_wheel_move());
+        });
+
+        inputs.track();
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// Appends every frame's inputs to `ResrcRecording`.
+#[derive(Default)]
+pub struct Recorder;
+
+impl<'a> System<'a> for Recorder {
+    type SystemData = (
+        ReadExpect<'a, ResrcInputState>,
+        ReadExpect<'a, ResrcDeltaTime>,
+        WriteExpect<'a, ResrcRecording>,
     );
 
-    fn run(&mut self, (rl, mut mouse): Self::SystemData) {
-        mouse.update(&rl);
+    fn run(&mut self, (inputs, delta, mut recording): Self::SystemData) {
+        recording.push(&inputs, delta.0);
+    }
+}
+
+/// Stands in for `Inputs`, feeding `ResrcRecording` back frame by frame,
+/// delta times included.
+///
+/// Doesn't need a window: a recorded session can be replayed headless.
+#[derive(Default)]
+pub struct Replayer {
+    frame: usize,
+}
+
+impl Replayer {
+    /// True once every frame of the recording has been replayed.
+    pub fn is_done(&self, recording: &ResrcRecording) -> bool {
+        self.frame >= recording.len()
+    }
+}
+
+impl<'a> System<'a> for Replayer {
+    type SystemData = (
+        ReadExpect<'a, ResrcRecording>,
+        WriteExpect<'a, ResrcDeltaTime>,
+        WriteExpect<'a, ResrcInputState>,
+    );
+
+    fn run(&mut self, (recording, mut delta, mut inputs): Self::SystemData) {
+        match recording.replay(self.frame, &mut inputs) {
+            Some(d) => {
+                delta.0 = d;
+                self.frame += 1;
+            }
+            // Past the end, the world is left to idle.
+            None => {
+                for i in Input::all() {
+                    i.set(&mut inputs, false, false, false);
+                }
+                inputs.mouse_mut().set_wheel(0);
+                inputs.track();
+            }
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        components::prelude::*, maths::prelude::*, systems::prelude::*, voxel::VoxelModel,
+    };
+    use raylib::prelude::Camera3D;
+    use std::collections::HashSet;
+
+    const SCREEN: (i32, i32) = (800, 600);
+    const DELTA: f32 = 1. / 60.;
+
+    fn camera() -> ResrcCamera {
+        let inner = Camera3D::perspective(
+            (0., 0., 0.).into(),
+            (0., 0., 0.).into(),
+            (0., 1., 0.).into(),
+            60.,
+        );
+        ResrcCamera::new(inner, ResrcCameraMode::RTS)
+    }
+
+    fn cube() -> VoxelModel {
+        let mut model = VoxelModel::default();
+        for x in 0..2 {
+            for y in 0..2 {
+                for z in 0..2 {
+                    model.set(&(x, y, z).into(), 1);
+                }
+            }
+        }
+        model.remesh();
+
+        model
+    }
+
+    /// Records a left-button drag from `from` to `to`.
+    fn record_box_selection(from: Vec2i, to: Vec2i) -> ResrcRecording {
+        let mut recording = ResrcRecording::default();
+        let mut inputs = ResrcInputState::default();
+        inputs.set_screen_size(SCREEN.into());
+        inputs.set_cursor_inside(true);
+        let left = Input::Mouse(0);
+
+        let mut frame = |pos: Vec2i, pressed, released, down| {
+            inputs.mouse_mut().set_position(pos);
+            left.set(&mut inputs, pressed, released, down);
+            recording.push(&inputs, DELTA);
+        };
+
+        frame(from, false, false, false);
+        frame(from, true, false, true);
+        for i in 1..=10 {
+            let pos = (
+                from.x + (to.x - from.x) * i / 10,
+                from.y + (to.y - from.y) * i / 10,
+            );
+            frame(pos.into(), false, false, true);
+        }
+        frame(to, false, true, false);
+        // The selection gets confirmed on the frame that follows the release.
+        frame(to, false, false, false);
+
+        recording
+    }
+
+    #[test]
+    fn replay_headless() {
+        let units: Vec<Vec3i> = vec![(28, 0, 34).into(), (34, 0, 28).into(), (45, 0, 45).into()];
+
+        // Where the units show up on screen, as seen from the default RTS
+        // camera.
+        let screen: Vec2 = (SCREEN.0 as f32, SCREEN.1 as f32).into();
+        let mut cam = camera();
+        cam.update(
+            &ResrcInputState::default(),
+            &ResrcBindings::default(),
+            &ResrcDeltaTime(DELTA),
+        );
+        let on_screen: Vec<_> = units
+            .iter()
+            .map(|pos| {
+                let center = (pos.x as f32 + 1., pos.y as f32 + 1., pos.z as f32 + 1.);
+                cam.world_to_screen(center.into(), screen).unwrap()
+            })
+            .collect();
+
+        // Box the first two units in, leaving the third one out.
+        let margin = 20.;
+        let min = (
+            on_screen[0].x.min(on_screen[1].x) - margin,
+            on_screen[0].y.min(on_screen[1].y) - margin,
+        );
+        let max = (
+            on_screen[0].x.max(on_screen[1].x) + margin,
+            on_screen[0].y.max(on_screen[1].y) + margin,
+        );
+        // Away from the borders, lest the camera scrolls mid-drag.
+        assert!(min.0 > 20. && min.1 > 20. && max.0 < screen.x - 20. && max.1 < screen.y - 20.);
+        let third = on_screen[2];
+        assert!(third.x < min.0 || third.x > max.0 || third.y < min.1 || third.y > max.1);
+
+        let recording = record_box_selection(
+            (min.0 as i32, min.1 as i32).into(),
+            (max.0 as i32, max.1 as i32).into(),
+        );
+        // Go through serialization, as a bug report would.
+        let recording = ResrcRecording::from_json(&recording.to_json()).unwrap();
+
+        let mut world = World::new();
+        let mut replayer = Replayer::default();
+        let mut dispatcher = DispatcherBuilder::new()
+            .with(SysCamera::default(), "camera", &[])
+            .with(SysBoundingTree::default(), "bounding_tree", &[])
+            .with(
+                SysSelector::default(),
+                "selector",
+                &["camera", "bounding_tree"],
+            )
+            .build();
+        System::setup(&mut replayer, &mut world);
+        dispatcher.setup(&mut world);
+
+        world.insert(ResrcDeltaTime(0.));
+        world.insert(ResrcInputState::default());
+        world.insert(recording);
+        world.insert(ResrcBindings::default());
+        world.insert(ResrcControlGroups::default());
+        world.insert(ResrcSelectionEvents::new());
+        world.insert(ResrcBoundingTree::new());
+        world.insert(camera());
+
+        for pos in &units {
+            world
+                .create_entity()
+                .with(CompVoxelModel(cube()))
+                .with(CompGridPosition(*pos))
+                .with(CompInvalidated)
+                .build();
+        }
+
+        while !replayer.is_done(&world.read_resource::<ResrcRecording>()) {
+            replayer.run_now(&world);
+            dispatcher.dispatch(&world);
+            world.maintain();
+        }
+
+        let (grid_pos, selected) = (
+            world.read_storage::<CompGridPosition>(),
+            world.read_storage::<CompSelected>(),
+        );
+        let selected: HashSet<_> = (&grid_pos, &selected)
+            .join()
+            .map(|(pos, _)| pos.0)
+            .collect();
+        assert_eq!(units[..2].iter().copied().collect::<HashSet<_>>(), selected);
     }
 }
@@ -6,8 +6,10 @@ mod selector;
 
 pub mod prelude {
     pub use super::{
-        bounding_tree::BoundingTree as SysBoundingTree, camera::Camera as SysCamera,
-        inputs::Mouse as SysMouse, renderer::Renderer as SysRenderer,
+        bounding_tree::BoundingTree as SysBoundingTree,
+        camera::Camera as SysCamera,
+        inputs::{Inputs as SysInputs, Recorder as SysRecorder, Replayer as SysReplayer},
+        renderer::Renderer as SysRenderer,
         selector::Selector as SysSelector,
     };
 }
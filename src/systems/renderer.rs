@@ -1,6 +1,7 @@
 use crate::{components::prelude::*, maths::prelude::*, resources::prelude::*};
 use raylib::prelude::*;
 use specs::prelude::*;
+use std::collections::HashSet;
 
 // -----------------------------------------------------------------------------
 
@@ -22,13 +23,14 @@ impl<'a> System<'a> for Renderer {
         WriteExpect<'a, ResrcModelView>,
         WriteExpect<'a, ResrcProjection>,
         ReadExpect<'a, ResrcCamera>,
+        ReadExpect<'a, ResrcBoundingTree>,
         Entities<'a>,
         ReadStorage<'a, CompDirectShape>,
         WriteStorage<'a, CompModel3D>,
         ReadStorage<'a, CompTransform3D>,
         ReadStorage<'a, CompSelected>,
         ReadStorage<'a, CompColor>,
-        ReadStorage<'a, CompVoxelModel>,
+        WriteStorage<'a, CompVoxelModel>,
         ReadStorage<'a, CompGridPosition>,
     );
 
@@ -38,13 +40,14 @@ impl<'a> System<'a> for Renderer {
             mut m_view,
             mut m_proj,
             cam,
+            bt,
             entities,
             shapes,
             mut models,
             transforms,
             selected,
             colors,
-            voxels,
+            mut voxels,
             grid_pos,
         ) = sys_data;
 
@@ -53,13 +56,15 @@ impl<'a> System<'a> for Renderer {
         // let font = rl.read(|rl| rl.get_font_default());
         let (swidth, sheight) = rl.read(|rl| (rl.get_screen_width(), rl.get_screen_height()));
         let (x, y) = rl.read(|rl| (rl.get_mouse_x() as f32, rl.get_mouse_y() as f32));
-        // TODO(cmc): only render what's visible in the frustrum.
         rl.draw(&thread, |d| {
             d.clear_background(Color::DARKGRAY);
 
             let render_start = std::time::Instant::now();
             let mut render_voxels = 0;
             let mut render_triangles = 0;
+            let mut render_bytes = 0;
+            let mut render_drawn = 0;
+            let mut render_culled = 0;
             {
                 let mut d2 = d.begin_mode_3D(cam.raw());
 
@@ -69,34 +74,46 @@ impl<'a> System<'a> for Renderer {
                 *m_view.0 = *hacks::get_matrix_modelview();
                 *m_proj.0 = *hacks::get_matrix_projection();
 
-                for (e, CompVoxelModel(model), CompGridPosition(world_pos), &CompColor(color)) in
-                    (&entities, &voxels, &grid_pos, &colors).join()
+                // A degenerate frustum culls nothing, rather than everything.
+                let visible: Option<HashSet<_>> = Mat4::from(*m_proj.0 * *m_view.0)
+                    .to_frustum()
+                    .map(|frustum| bt.test_frustum(&frustum).collect());
+
+                for (e, mut voxel, pos) in (&entities, &mut voxels.restrict_mut(), &grid_pos).join()
                 {
+                    if visible
+                        .as_ref()
+                        .map_or(false, |visible| !visible.contains(&e))
+                    {
+                        render_culled += 1;
+                        continue;
+                    }
+                    render_drawn += 1;
+
                     // TODO(cmc): something smarter
-                    let color = if let Some(_) = selected.get(e) {
-                        Color::GOLD
+                    let tint = if let Some(_) = selected.get(e) {
+                        Some(Color::GOLD)
                     } else {
-                        color
+                        None
                     };
 
-                    use raylib::core::math::Vector3 as RayVector3;
-                    let dims: RayVector3 = (1., 1., 1.).into();
+                    // Only the chunks that changed since last frame get
+                    // re-meshed. Every mutable access to a flagged storage
+                    // counts as a modification: only ask for one when there's
+                    // work to do.
+                    if voxel.get_unchecked().0.is_dirty() {
+                        voxel.get_mut_unchecked().0.remesh();
+                    }
+                    let CompVoxelModel(model) = voxel.get_unchecked();
+                    let CompGridPosition(world_pos) = pos;
 
                     let model_stats = model.stats();
                     render_voxels += model_stats.nb_voxels;
                     render_triangles += model_stats.nb_triangles;
+                    render_bytes += model_stats.nb_bytes;
 
-                    let voxels: Vec<_> = model
-                        .iter()
-                        .filter(|(_, voxel)| *voxel)
-                        .map(|(pos, _)| **world_pos + *pos)
-                        .map(|pos| RayVector3::from((pos.x as f32, pos.y as f32, pos.z as f32)))
-                        .collect();
-                    for pos in voxels.iter() {
-                        d2.draw_cube_v(pos, dims, color);
-                    }
-                    for pos in voxels.iter() {
-                        d2.draw_cube_wires(pos, dims.x, dims.y, dims.z, Color::BLACK);
+                    for mesh in model.meshes() {
+                        hacks::draw_voxel_mesh(mesh, model.palette(), world_pos, tint);
                     }
                 }
 
@@ -146,12 +163,14 @@ impl<'a> System<'a> for Renderer {
             #[rustfmt::skip]
             imgui::draw_debug_info(
                 d,
-                10, sheight - 80, 150, 0, 10,
+                10, sheight - 110, 150, 0, 10,
                 "Rendering stats:".into(),
                 &[
                     format!("- Duration: {:?}", render_time).as_str(),
                     format!("- Voxels: {:#?}", render_voxels).as_str(),
                     format!("- Triangles: {:#?}", render_triangles).as_str(),
+                    format!("- Memory: {} KiB", render_bytes / 1024).as_str(),
+                    format!("- Models: {} drawn, {} culled", render_drawn, render_culled).as_str(),
                 ],
             );
 
@@ -164,6 +183,9 @@ impl<'a> System<'a> for Renderer {
                         "Camera mode [RTS]:".into(),
                         &[
                             "- Move with keys: W, A, S, D",
+                            "- Move with the screen edges",
+                            "- Middle mouse drag to pan",
+                            "- Rotate with keys: Q, E",
                             "- Mouse wheel to zoom"
                         ],
                     );
@@ -255,15 +277,28 @@ mod imgui {
 // -----------------------------------------------------------------------------
 
 mod hacks {
-    use crate::maths::prelude::*;
+    use crate::{
+        maths::prelude::*,
+        voxel::{VoxelMesh, VoxelPalette},
+    };
     use cgmath::Matrix4 as CGMat4;
-    use raylib::{core::math::Matrix as RMat4, ffi::Matrix as c_matrix};
+    use raylib::{color::Color, core::math::Matrix as RMat4, ffi::Matrix as c_matrix};
+    use std::os::raw::c_int;
 
     extern "C" {
         fn GetMatrixProjection() -> c_matrix;
         fn GetMatrixModelview() -> c_matrix;
+
+        fn rlBegin(mode: c_int);
+        fn rlEnd();
+        fn rlVertex3f(x: f32, y: f32, z: f32);
+        fn rlColor4ub(r: u8, g: u8, b: u8, a: u8);
+        fn rlCheckBufferLimit(v_count: c_int) -> bool;
+        fn rlglDraw();
     }
 
+    const RL_TRIANGLES: c_int = 0x0004;
+
     // TODO(cmc): def shouldnt be here
     #[rustfmt::skip]
     fn to_mat4(m: RMat4) -> Mat4 {
@@ -284,6 +319,63 @@ mod hacks {
         let m: RMat4 = unsafe { GetMatrixModelview() }.into();
         to_mat4(m)
     }
+
+    /// Pushes the triangles of `mesh` straight into rlgl's batch, shading
+    /// each face according to its normal so that the model keeps some depth
+    /// until we get proper lighting.
+    ///
+    /// Colors come from `palette`, unless a `tint` overrides them.
+    ///
+    /// Must be called from within 3D mode.
+    pub fn draw_voxel_mesh(
+        mesh: &VoxelMesh,
+        palette: &VoxelPalette,
+        offset: &Vec3i,
+        tint: Option<Color>,
+    ) {
+        // Stay well under rlgl's batch limits, flushing in between if needed.
+        const TRIANGLES_PER_BATCH: usize = 1024;
+
+        let shade = |normal: &Vec3| -> f32 {
+            match (normal.x as i32, normal.y as i32, normal.z as i32) {
+                (_, 1, _) => 1.0,
+                (_, -1, _) => 0.5,
+                (1, _, _) | (-1, _, _) => 0.8,
+                _ => 0.65,
+            }
+        };
+
+        let indices = mesh.indices.as_slice();
+        for batch in indices.chunks(TRIANGLES_PER_BATCH * 3) {
+            unsafe {
+                if rlCheckBufferLimit(batch.len() as c_int) {
+                    rlglDraw();
+                }
+
+                rlBegin(RL_TRIANGLES);
+                for &idx in batch {
+                    let pos = &mesh.positions[idx as usize];
+                    let shade = shade(&mesh.normals[idx as usize]);
+                    let color = tint.unwrap_or_else(|| {
+                        let c = palette[mesh.colors[idx as usize]];
+                        Color::new(c.r, c.g, c.b, c.a)
+                    });
+                    rlColor4ub(
+                        (color.r as f32 * shade) as u8,
+                        (color.g as f32 * shade) as u8,
+                        (color.b as f32 * shade) as u8,
+                        color.a,
+                    );
+                    rlVertex3f(
+                        pos.x + offset.x as f32,
+                        pos.y + offset.y as f32,
+                        pos.z + offset.z as f32,
+                    );
+                }
+                rlEnd();
+            }
+        }
+    }
 }
 
 // -----------------------------------------------------------------------------
@@ -1,25 +1,74 @@
 use crate::{components::prelude::*, maths::prelude::*, resources::prelude::*};
-use anyhow::{anyhow, Error as AnyError, Result as AnyResult};
-use collision::{Frustum, Plane};
 use raylib::{color::Color, consts::CameraMode};
 use specs::prelude::*;
+use std::collections::HashSet;
 
 // -----------------------------------------------------------------------------
 
+// The trailing boolean flags selections started with a double-click.
 enum SelectorState {
     Idle,
-    Selecting(Entity, Vec2i),
-    Confirmed(Entity, Vec2i, Vec2i),
+    Selecting(Entity, Vec2i, bool),
+    Confirmed(Entity, Vec2i, Vec2i, SelectionMode, Gesture),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Gesture {
+    Drag,
+    Click,
+    DoubleClick,
+}
+
+/// How a new pick combines with the current selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionMode {
+    Replace,
+    Add,
+    Toggle,
+    Subtract,
+}
+
+impl SelectionMode {
+    /// Subtracting takes precedence over toggling, which takes precedence
+    /// over adding.
+    pub fn from_inputs(inputs: &ResrcInputState, bindings: &ResrcBindings) -> Self {
+        if bindings.is_down(inputs, Action::SelectSubtract) {
+            Self::Subtract
+        } else if bindings.is_down(inputs, Action::SelectToggle) {
+            Self::Toggle
+        } else if bindings.is_down(inputs, Action::SelectAdd) {
+            Self::Add
+        } else {
+            Self::Replace
+        }
+    }
+
+    pub fn apply(self, selection: &mut HashSet<Entity>, picked: &HashSet<Entity>) {
+        match self {
+            Self::Replace => *selection = picked.clone(),
+            Self::Add => selection.extend(picked),
+            Self::Toggle => {
+                for e in picked {
+                    if !selection.remove(e) {
+                        selection.insert(*e);
+                    }
+                }
+            }
+            Self::Subtract => selection.retain(|e| !picked.contains(e)),
+        }
+    }
 }
 
 pub struct Selector {
     state: SelectorState,
+    hovered: Option<Entity>,
 }
 
 impl Default for Selector {
     fn default() -> Self {
         Self {
             state: SelectorState::Idle,
+            hovered: None,
         }
     }
 }
@@ -28,34 +77,83 @@ impl Default for Selector {
 impl<'a> System<'a> for Selector {
     type SystemData = (
         Entities<'a>,
-        ReadExpect<'a, ResrcRaylib>,
-        ReadExpect<'a, ResrcMouseState>,
+        ReadExpect<'a, ResrcInputState>,
+        ReadExpect<'a, ResrcBindings>,
         ReadExpect<'a, ResrcBoundingTree>,
-        ReadExpect<'a, ResrcCamera>,
-        ReadExpect<'a, ResrcProjection>,
-        ReadExpect<'a, ResrcModelView>,
+        WriteExpect<'a, ResrcCamera>,
+        WriteExpect<'a, ResrcControlGroups>,
+        Write<'a, ResrcSelectionEvents>,
+        ReadStorage<'a, CompVoxelModel>,
+        ReadStorage<'a, CompGridPosition>,
+        ReadStorage<'a, CompUnitType>,
         WriteStorage<'a, CompDirectShape>,
         WriteStorage<'a, CompSelected>,
         WriteStorage<'a, CompColor>,
     );
 
     fn run(&mut self, sys_data: Self::SystemData) {
-        let (entities, rl, mouse, bt, cam, m_proj, m_view, mut shapes, mut selected, mut colors) =
-            sys_data;
+        let (
+            entities,
+            inputs,
+            bindings,
+            bt,
+            mut cam,
+            mut groups,
+            mut events,
+            voxels,
+            grid_pos,
+            unit_types,
+            mut shapes,
+            mut selected,
+            mut colors,
+        ) = sys_data;
 
         if cam.mode() != ResrcCameraMode::RTS {
             return;
         }
 
+        groups.prune(&entities);
+
+        let mouse = inputs.mouse();
+        let screen = inputs.screen_size();
+        let screen: Vec2 = (screen.x as f32, screen.y as f32).into();
+        let cursor = mouse.position();
+        let hovered = cam
+            .screen_to_ray((cursor.x as f32, cursor.y as f32).into(), screen)
+            .and_then(|r| pick(&bt, &voxels, &grid_pos, &r));
+        if hovered != self.hovered {
+            if let Some(e) = self.hovered {
+                events.single_write(SelectionEvent::HoverEnded(e));
+            }
+            if let Some(e) = hovered {
+                events.single_write(SelectionEvent::HoverStarted(e));
+            }
+            self.hovered = hovered;
+        }
+
         match self.state {
             SelectorState::Idle => {
-                if mouse.is_pressed(0) {
+                control_groups(
+                    &entities,
+                    &inputs,
+                    &bindings,
+                    &bt,
+                    &mut groups,
+                    &mut cam,
+                    &mut selected,
+                    &mut events,
+                );
+
+                if bindings.is_pressed(&inputs, Action::SelectBox) {
                     let pos = mouse.position();
                     let e = entities.build_entity().build();
-                    self.state = SelectorState::Selecting(e, pos);
+                    let double_click = bindings
+                        .mouse_button(Action::SelectBox)
+                        .map_or(false, |button| mouse.is_double_clicked(button));
+                    self.state = SelectorState::Selecting(e, pos, double_click);
                 }
             }
-            SelectorState::Selecting(e, mut pos1) => {
+            SelectorState::Selecting(e, mut pos1, double_click) => {
                 let mut pos2 = mouse.position();
                 let mut dim = *pos2 - *pos1;
                 if dim.x.is_negative() {
@@ -75,105 +173,353 @@ impl<'a> System<'a> for Selector {
                 shapes.insert(e, rect).unwrap();
                 colors.insert(e, color).unwrap();
 
-                if mouse.is_released(0) {
-                    self.state = SelectorState::Confirmed(e, pos1, dim.into());
+                if bindings.is_released(&inputs, Action::SelectBox) {
+                    let is_click = match bindings.mouse_button(Action::SelectBox) {
+                        Some(button) => mouse.is_clicked(button),
+                        // Keyboard-only bindings: all there is to go by is
+                        // the size of the box.
+                        None => {
+                            let threshold = mouse.drag_threshold();
+                            dim.x <= threshold && dim.y <= threshold
+                        }
+                    };
+                    let gesture = match (is_click, double_click) {
+                        (true, true) => Gesture::DoubleClick,
+                        (true, false) => Gesture::Click,
+                        (false, _) => Gesture::Drag,
+                    };
+                    let mode = SelectionMode::from_inputs(&inputs, &bindings);
+                    self.state = SelectorState::Confirmed(e, pos1, dim.into(), mode, gesture);
                 }
             }
-            SelectorState::Confirmed(e, pos, dim) => {
+            SelectorState::Confirmed(e, pos, dim, mode, gesture) => {
                 entities.delete(e).unwrap();
                 self.state = SelectorState::Idle;
 
-                let mat = *m_proj.0 * *m_view.0;
-                let mat = mat.invert().unwrap();
-                let (swidth, sheight) =
-                    rl.read(|rl| (rl.get_screen_width() as f32, rl.get_screen_height() as f32));
-
-                let corners = &[
-                    (pos.x as f32, pos.y as f32),
-                    (pos.x as f32 + dim.x as f32, pos.y as f32),
-                    (pos.x as f32 + dim.x as f32, pos.y as f32 + dim.y as f32),
-                    (pos.x as f32, pos.y as f32 + dim.y as f32),
-                ];
-
-                let mut corners: Vec<_> = corners
-                    .into_iter()
-                    .map(|pos| {
-                        let (x, y) = ((2. * pos.0) / swidth - 1., 1. - (2. * pos.1) / sheight);
-
-                        let near: Point3 = {
-                            let pos: Vec4 = (x, y, 0.999, 1.).into();
-                            let pos = mat * *pos;
-                            (pos.x / pos.w, pos.y / pos.w, pos.z / pos.w).into()
-                        };
-                        let far: Point3 = {
-                            let pos: Vec4 = (x, y, 0.99999, 1.).into();
-                            let pos = mat * *pos;
-                            (pos.x / pos.w, pos.y / pos.w, pos.z / pos.w).into()
-                        };
-
-                        (near, far)
-                    })
-                    .collect();
-
-                selected.clear();
-                corners
-                    .iter()
-                    .map(|&(near, far)| {
-                        let dir = (*far - *near).normalize();
-                        collision::Ray3::new(*near, dir)
-                    })
-                    .for_each(|r| {
-                        for e in bt.test_ray(&r).collect::<Vec<_>>() {
-                            selected.insert(e, CompSelected).unwrap();
-                        }
-                    });
-
-                // NOTE(cmc): X and Z axes are flipped within collision's
-                // `Frustum`.
-                for corner in &mut corners {
-                    corner.0.x *= -1.;
-                    corner.0.z *= -1.;
-                    corner.1.x *= -1.;
-                    corner.1.z *= -1.;
-                }
-                let planes = vec![
-                    /* lft */ (corners[0].0, corners[0].1, corners[3].1, corners[3].0),
-                    /* rgt */ (corners[1].0, corners[1].1, corners[2].1, corners[2].0),
-                    /* btm */ (corners[3].0, corners[3].1, corners[2].1, corners[2].0),
-                    /* top */ (corners[0].0, corners[0].1, corners[1].1, corners[1].0),
-                    /* nar */ (corners[0].0, corners[1].0, corners[2].0, corners[3].0),
-                    /* far */ (corners[0].1, corners[1].1, corners[2].1, corners[3].1),
-                ];
-
-                let frustum = (|| {
-                    macro_rules! plane {
-                        ($p1:expr, $p2:expr, $p3:expr) => {
-                            Plane::from_points($p1, $p2, $p3)
-                                .ok_or(anyhow!("illegal plane"))?
-                                .normalize()
-                                .ok_or(anyhow!("illegal plane"))
-                        };
+                let mut picked = HashSet::new();
+                match gesture {
+                    Gesture::DoubleClick => {
+                        let view_proj = cam.view_projection(screen);
+                        picked.extend(hovered.into_iter().flat_map(|hovered| {
+                            same_type_on_screen(&bt, &unit_types, &view_proj, hovered)
+                        }));
+                    }
+                    // A click would make for a degenerate frustum: pick
+                    // whatever lies under the cursor instead.
+                    Gesture::Click => picked.extend(hovered),
+                    Gesture::Drag => {
+                        let view_proj = cam.view_projection(screen);
+                        picked.extend(box_select(&bt, &view_proj, screen, pos, dim));
                     }
-                    let f = Frustum::new(
-                        /* nar */ plane!(*planes[4].2, *planes[4].1, *planes[4].0)?,
-                        /* far */ plane!(*planes[5].0, *planes[5].1, *planes[5].2)?,
-                        /* lft */ plane!(*planes[0].0, *planes[0].1, *planes[0].2)?,
-                        /* rgt */ plane!(*planes[1].2, *planes[1].1, *planes[1].0)?,
-                        /* btm */ plane!(*planes[2].0, *planes[2].1, *planes[2].2)?,
-                        /* top */ plane!(*planes[3].2, *planes[3].1, *planes[3].0)?,
-                    );
-                    Ok::<_, AnyError>(f)
-                })();
-
-                if frustum.is_err() {
-                    return;
                 }
-                let frustum = frustum.unwrap();
 
-                for e in bt.test_frustum(&frustum).collect::<Vec<_>>() {
-                    selected.insert(e, CompSelected).unwrap();
+                let mut selection: HashSet<_> =
+                    (&entities, &selected).join().map(|(e, _)| e).collect();
+                mode.apply(&mut selection, &picked);
+
+                replace_selection(&entities, &mut selected, &mut events, selection);
+            }
+        }
+    }
+}
+
+/// Returns every entity within the sub-frustum delimited by the selection box.
+fn box_select(
+    bt: &ResrcBoundingTree,
+    view_proj: &Mat4,
+    screen: Vec2,
+    pos: Vec2i,
+    dim: Vec2i,
+) -> HashSet<Entity> {
+    let min: Vec2 = (pos.x as f32, pos.y as f32).into();
+    let max: Vec2 = ((pos.x + dim.x) as f32, (pos.y + dim.y) as f32).into();
+    match view_proj.to_screen_frustum(screen, min, max) {
+        Some(frustum) => bt.test_frustum(&frustum).collect(),
+        None => HashSet::new(),
+    }
+}
+
+/// Returns `e` along with every entity on screen of the same unit type.
+fn same_type_on_screen(
+    bt: &ResrcBoundingTree,
+    unit_types: &ReadStorage<CompUnitType>,
+    view_proj: &Mat4,
+    e: Entity,
+) -> HashSet<Entity> {
+    let mut picked = HashSet::new();
+    picked.insert(e);
+
+    if let (Some(unit_type), Some(frustum)) = (unit_types.get(e), view_proj.to_frustum()) {
+        picked.extend(
+            bt.test_frustum(&frustum)
+                .filter(|other| unit_types.get(*other) == Some(unit_type)),
+        );
+    }
+
+    picked
+}
+
+/// Assign+group stores the current selection, group recalls it and
+/// double-tapping a group centers the camera on it (by default: Ctrl+digit,
+/// digit and digit twice, respectively).
+#[allow(clippy::too_many_arguments)]
+fn control_groups(
+    entities: &Entities,
+    inputs: &ResrcInputState,
+    bindings: &ResrcBindings,
+    bt: &ResrcBoundingTree,
+    groups: &mut ResrcControlGroups,
+    cam: &mut ResrcCamera,
+    selected: &mut WriteStorage<CompSelected>,
+    events: &mut ResrcSelectionEvents,
+) {
+    let group = match Action::CONTROL_GROUPS
+        .iter()
+        .position(|&action| bindings.is_pressed(inputs, action))
+    {
+        Some(idx) => idx + 1,
+        None => return,
+    };
+
+    if bindings.is_down(inputs, Action::ControlGroupAssign) {
+        groups.assign(group, (entities, &*selected).join().map(|(e, _)| e));
+        return;
+    }
+
+    // Recalling an empty group is a no-op rather than a way to deselect.
+    let selection = groups.get(group).clone();
+    if selection.is_empty() {
+        return;
+    }
+    replace_selection(entities, selected, events, selection);
+
+    if groups.recall(group, inputs.time()) {
+        let centers: Vec<_> = groups
+            .get(group)
+            .iter()
+            .filter_map(|&e| bt.bounds(e))
+            .map(|(min, max)| (*min + max.to_vec()) / 2.)
+            .collect();
+        if !centers.is_empty() {
+            let centroid = centers
+                .iter()
+                .fold(CGPoint3::new(0., 0., 0.), |acc, c| acc + c.to_vec())
+                / centers.len() as f32;
+            cam.focus(centroid.into());
+        }
+    }
+}
+
+/// Overwrites the current selection, publishing whatever changed.
+fn replace_selection(
+    entities: &Entities,
+    selected: &mut WriteStorage<CompSelected>,
+    events: &mut ResrcSelectionEvents,
+    selection: HashSet<Entity>,
+) {
+    let previous: HashSet<_> = (entities, &*selected).join().map(|(e, _)| e).collect();
+    let added: Vec<_> = selection.difference(&previous).copied().collect();
+    let removed: Vec<_> = previous.difference(&selection).copied().collect();
+
+    selected.clear();
+    for e in selection {
+        selected.insert(e, CompSelected).unwrap();
+    }
+
+    if !added.is_empty() || !removed.is_empty() {
+        events.single_write(SelectionEvent::SelectionChanged { added, removed });
+    }
+}
+
+/// Returns the closest entity hit by `r`.
+///
+/// Bounding boxes only give a rough idea of what's under the cursor: voxel
+/// models get raycast voxel by voxel so that clicking through their empty
+/// space doesn't pick them.
+fn pick(
+    bt: &ResrcBoundingTree,
+    voxels: &ReadStorage<CompVoxelModel>,
+    grid_pos: &ReadStorage<CompGridPosition>,
+    r: &collision::Ray3<f32>,
+) -> Option<Entity> {
+    let mut closest: Option<(Entity, f32)> = None;
+    for (e, bounds_dist) in bt.test_ray(r) {
+        // Hits are sorted by distance to their bounds, which can only be
+        // closer than the actual hit.
+        if closest.map_or(false, |(_, dist)| bounds_dist > dist) {
+            break;
+        }
+
+        let dist = match (voxels.get(e), grid_pos.get(e)) {
+            (Some(CompVoxelModel(model)), Some(CompGridPosition(pos))) => {
+                match model.raycast(pos, r, std::f32::MAX) {
+                    Some(hit) => hit.distance,
+                    None => continue,
                 }
             }
+            _ => bounds_dist,
+        };
+        if closest.map_or(true, |(_, closest)| dist < closest) {
+            closest = Some((e, dist));
         }
     }
+
+    closest.map(|(e, _)| e)
+}
+
+// -----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raylib::consts::KeyboardKey::{self, *};
+
+    fn mode(keys: &[KeyboardKey]) -> SelectionMode {
+        let mut inputs = ResrcInputState::default();
+        keys.iter()
+            .for_each(|&key| Input::Key(key).set(&mut inputs, false, false, true));
+        SelectionMode::from_inputs(&inputs, &ResrcBindings::default())
+    }
+
+    fn set(entities: &[Entity], indices: &[usize]) -> HashSet<Entity> {
+        indices.iter().map(|&i| entities[i]).collect()
+    }
+
+    #[test]
+    fn mode_from_inputs() {
+        assert_eq!(SelectionMode::Replace, mode(&[]));
+        assert_eq!(SelectionMode::Add, mode(&[KEY_LEFT_SHIFT]));
+        assert_eq!(SelectionMode::Add, mode(&[KEY_RIGHT_SHIFT]));
+        assert_eq!(SelectionMode::Toggle, mode(&[KEY_LEFT_CONTROL]));
+        assert_eq!(SelectionMode::Subtract, mode(&[KEY_RIGHT_ALT]));
+
+        // Subtract > Toggle > Add.
+        assert_eq!(
+            SelectionMode::Toggle,
+            mode(&[KEY_LEFT_SHIFT, KEY_LEFT_CONTROL])
+        );
+        assert_eq!(
+            SelectionMode::Subtract,
+            mode(&[KEY_LEFT_SHIFT, KEY_LEFT_ALT])
+        );
+        assert_eq!(
+            SelectionMode::Subtract,
+            mode(&[KEY_LEFT_SHIFT, KEY_RIGHT_CONTROL, KEY_LEFT_ALT])
+        );
+
+        // Unbound keys change nothing.
+        assert_eq!(SelectionMode::Replace, mode(&[KEY_Z]));
+    }
+
+    #[test]
+    fn mode_apply() {
+        let mut world = World::new();
+        let entities: Vec<_> = (0..4).map(|_| world.create_entity().build()).collect();
+        let picked = set(&entities, &[1, 2]);
+
+        let cases = [
+            (SelectionMode::Replace, set(&entities, &[1, 2])),
+            (SelectionMode::Add, set(&entities, &[0, 1, 2])),
+            (SelectionMode::Toggle, set(&entities, &[0, 2])),
+            (SelectionMode::Subtract, set(&entities, &[0])),
+        ];
+        for (mode, expected) in &cases {
+            let mut selection = set(&entities, &[0, 1]);
+            mode.apply(&mut selection, &picked);
+            assert_eq!(expected, &selection, "{:?}", mode);
+        }
+
+        // Toggling twice is a no-op.
+        let mut selection = set(&entities, &[0, 1]);
+        SelectionMode::Toggle.apply(&mut selection, &picked);
+        SelectionMode::Toggle.apply(&mut selection, &picked);
+        assert_eq!(set(&entities, &[0, 1]), selection);
+    }
+
+    /// Runs `control_groups` for a frame during which `keys` are held, the
+    /// last one having just been pressed.
+    fn control_groups_with(world: &World, bindings: &ResrcBindings, keys: &[KeyboardKey]) {
+        let mut inputs = ResrcInputState::default();
+        for (i, &key) in keys.iter().enumerate() {
+            Input::Key(key).set(&mut inputs, i + 1 == keys.len(), false, true);
+        }
+
+        control_groups(
+            &world.entities(),
+            &inputs,
+            bindings,
+            &world.read_resource(),
+            &mut world.write_resource(),
+            &mut world.write_resource(),
+            &mut world.write_storage(),
+            &mut world.write_resource(),
+        );
+    }
+
+    fn selection(world: &World) -> HashSet<Entity> {
+        let selected = world.read_storage::<CompSelected>();
+        (&world.entities(), &selected)
+            .join()
+            .map(|(e, _)| e)
+            .collect()
+    }
+
+    fn select(world: &World, e: Entity) {
+        let mut selected = world.write_storage::<CompSelected>();
+        selected.clear();
+        selected.insert(e, CompSelected).unwrap();
+    }
+
+    #[test]
+    fn control_groups_follow_bindings() {
+        let mut world = World::new();
+        world.register::<CompSelected>();
+        world.insert(ResrcBoundingTree::new());
+        world.insert(ResrcControlGroups::default());
+        world.insert(ResrcSelectionEvents::new());
+        let inner = raylib::prelude::Camera3D::perspective(
+            (0., 10., 10.).into(),
+            (0., 0., 0.).into(),
+            (0., 1., 0.).into(),
+            60.,
+        );
+        world.insert(ResrcCamera::new(inner, ResrcCameraMode::RTS));
+        let (e0, e1) = (world.create_entity().build(), world.create_entity().build());
+
+        let mut bindings = ResrcBindings::default();
+        select(&world, e0);
+        control_groups_with(&world, &bindings, &[KEY_LEFT_CONTROL, KEY_ONE]);
+        select(&world, e1);
+        control_groups_with(&world, &bindings, &[KEY_ONE]);
+        assert_eq!(vec![e0], selection(&world).into_iter().collect::<Vec<_>>());
+
+        // Once rebound, Ctrl is just another key.
+        bindings.rebind(Action::ControlGroupAssign, vec![Input::Key(KEY_LEFT_SHIFT)]);
+        bindings.rebind(Action::ControlGroup2, vec![Input::Key(KEY_F5)]);
+        select(&world, e1);
+        control_groups_with(&world, &bindings, &[KEY_LEFT_CONTROL, KEY_F5]);
+        assert!(world
+            .read_resource::<ResrcControlGroups>()
+            .get(2)
+            .is_empty());
+        control_groups_with(&world, &bindings, &[KEY_LEFT_SHIFT, KEY_TWO]);
+        assert!(world
+            .read_resource::<ResrcControlGroups>()
+            .get(2)
+            .is_empty());
+        control_groups_with(&world, &bindings, &[KEY_LEFT_SHIFT, KEY_F5]);
+        assert_eq!(
+            vec![e1],
+            world
+                .read_resource::<ResrcControlGroups>()
+                .get(2)
+                .iter()
+                .copied()
+                .collect::<Vec<_>>()
+        );
+
+        select(&world, e0);
+        control_groups_with(&world, &bindings, &[KEY_F5]);
+        assert_eq!(vec![e1], selection(&world).into_iter().collect::<Vec<_>>());
+    }
 }
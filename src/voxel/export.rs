@@ -0,0 +1,459 @@
+use super::{VoxelMesh, VoxelModel};
+use crate::maths::prelude::*;
+use serde_json::json;
+use std::{borrow::Cow, fmt::Write};
+
+// -----------------------------------------------------------------------------
+
+// All exporters work off the cached meshes; chunks that changed since the last
+// `remesh` get meshed on the fly.
+//
+// Positions are in model space, faces wind counter-clockwise and colors come
+// straight from the palette of the model.
+
+impl VoxelModel {
+    /// Merges the meshes of every chunk into a single one, in a deterministic
+    /// order so that exports can be diffed.
+    fn merged_mesh(&self) -> VoxelMesh {
+        let mut chunks: Vec<_> = self.chunks.iter().collect();
+        chunks.sort_by_key(|(pos, _)| (pos.z, pos.y, pos.x));
+
+        let mut merged = VoxelMesh::default();
+        for (pos, c) in chunks {
+            let mesh = c
+                .mesh
+                .as_ref()
+                .map_or_else(|| Cow::Owned(self.mesh_chunk(pos)), Cow::Borrowed);
+            let base = merged.positions.len() as u32;
+            merged.positions.extend_from_slice(&mesh.positions);
+            merged.normals.extend_from_slice(&mesh.normals);
+            merged.colors.extend_from_slice(&mesh.colors);
+            merged.indices.extend(mesh.indices.iter().map(|i| base + i));
+        }
+
+        merged
+    }
+
+    /// Exports the mesh of the model as a Wavefront OBJ.
+    ///
+    /// Colors are stored as vertex colors (`v x y z r g b`), an extension that
+    /// Blender & MeshLab understand.
+    pub fn to_obj(&self) -> String {
+        let mesh = self.merged_mesh();
+        let mut out = String::with_capacity(mesh.positions.len() * 64);
+
+        // Writing into a `String` cannot fail.
+        writeln!(out, "# {} triangles", mesh.nb_triangles()).unwrap();
+        for (p, &c) in mesh.positions.iter().zip(&mesh.colors) {
+            let c = self.palette[c];
+            writeln!(
+                out,
+                "v {} {} {} {} {} {}",
+                p.x,
+                p.y,
+                p.z,
+                c.r as f32 / 255.0,
+                c.g as f32 / 255.0,
+                c.b as f32 / 255.0,
+            )
+            .unwrap();
+        }
+        for n in &mesh.normals {
+            writeln!(out, "vn {} {} {}", n.x, n.y, n.z).unwrap();
+        }
+        // OBJ indices are 1-based.
+        for [a, b, c] in mesh.triangles().map(|t| [t[0] + 1, t[1] + 1, t[2] + 1]) {
+            writeln!(out, "f {}//{} {}//{} {}//{}", a, a, b, b, c, c).unwrap();
+        }
+
+        out
+    }
+
+    /// Exports the mesh of the model as an ASCII PLY, with per-vertex normals
+    /// and colors.
+    pub fn to_ply(&self) -> String {
+        let mesh = self.merged_mesh();
+        let mut out = String::with_capacity(mesh.positions.len() * 64);
+
+        writeln!(out, "ply").unwrap();
+        writeln!(out, "format ascii 1.0").unwrap();
+        writeln!(out, "element vertex {}", mesh.positions.len()).unwrap();
+        for prop in &["x", "y", "z", "nx", "ny", "nz"] {
+            writeln!(out, "property float {}", prop).unwrap();
+        }
+        for prop in &["red", "green", "blue", "alpha"] {
+            writeln!(out, "property uchar {}", prop).unwrap();
+        }
+        writeln!(out, "element face {}", mesh.nb_triangles()).unwrap();
+        writeln!(out, "property list uchar uint vertex_indices").unwrap();
+        writeln!(out, "end_header").unwrap();
+
+        for ((p, n), &c) in mesh.positions.iter().zip(&mesh.normals).zip(&mesh.colors) {
+            let c = self.palette[c];
+            writeln!(
+                out,
+                "{} {} {} {} {} {} {} {} {} {}",
+                p.x, p.y, p.z, n.x, n.y, n.z, c.r, c.g, c.b, c.a
+            )
+            .unwrap();
+        }
+        for [a, b, c] in mesh.triangles() {
+            writeln!(out, "3 {} {} {}", a, b, c).unwrap();
+        }
+
+        out
+    }
+
+    /// Exports the mesh of the model as a binary glTF (`.glb`), colors being
+    /// stored as `COLOR_0`.
+    pub fn to_glb(&self) -> Vec<u8> {
+        const ARRAY_BUFFER: u32 = 34962;
+        const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+        const FLOAT: u32 = 5126;
+        const UNSIGNED_BYTE: u32 = 5121;
+        const UNSIGNED_INT: u32 = 5125;
+
+        let mesh = self.merged_mesh();
+
+        let vec3_bytes = |vs: &[Vec3]| -> Vec<u8> {
+            vs.iter()
+                .map(|v| vec![v.x, v.y, v.z])
+                .flatten()
+                .map(|f| f.to_le_bytes().to_vec())
+                .flatten()
+                .collect()
+        };
+        let attributes = [
+            (vec3_bytes(&mesh.positions), ARRAY_BUFFER),
+            (vec3_bytes(&mesh.normals), ARRAY_BUFFER),
+            (
+                mesh.colors
+                    .iter()
+                    .map(|&c| {
+                        let c = self.palette[c];
+                        vec![c.r, c.g, c.b, c.a]
+                    })
+                    .flatten()
+                    .collect(),
+                ARRAY_BUFFER,
+            ),
+            (
+                mesh.indices
+                    .iter()
+                    .map(|i| i.to_le_bytes().to_vec())
+                    .flatten()
+                    .collect(),
+                ELEMENT_ARRAY_BUFFER,
+            ),
+        ];
+
+        // Every attribute is 4-byte aligned already, so they can be laid out
+        // back to back.
+        let mut bin = Vec::with_capacity(attributes.iter().map(|(b, _)| b.len()).sum());
+        let mut views = Vec::with_capacity(attributes.len());
+        for (bytes, target) in &attributes {
+            views.push(json!({
+                "buffer": 0,
+                "byteOffset": bin.len(),
+                "byteLength": bytes.len(),
+                "target": target,
+            }));
+            bin.extend_from_slice(bytes);
+        }
+
+        let (min, max) = mesh.positions.iter().fold(
+            ([std::f32::MAX; 3], [std::f32::MIN; 3]),
+            |(min, max), p| {
+                (
+                    [min[0].min(p.x), min[1].min(p.y), min[2].min(p.z)],
+                    [max[0].max(p.x), max[1].max(p.y), max[2].max(p.z)],
+                )
+            },
+        );
+
+        let nb_vertices = mesh.positions.len();
+        let gltf = if mesh.indices.is_empty() {
+            // glTF forbids empty accessors: an empty model is an empty scene.
+            json!({
+                "asset": { "version": "2.0", "generator": "rts" },
+                "scene": 0,
+                "scenes": [{ "nodes": [] }],
+            })
+        } else {
+            json!({
+                "asset": { "version": "2.0", "generator": "rts" },
+                "scene": 0,
+                "scenes": [{ "nodes": [0] }],
+                "nodes": [{ "mesh": 0 }],
+                "meshes": [{
+                    "primitives": [{
+                        "attributes": { "POSITION": 0, "NORMAL": 1, "COLOR_0": 2 },
+                        "indices": 3,
+                        "mode": 4,
+                    }],
+                }],
+                "accessors": [
+                    {
+                        "bufferView": 0,
+                        "componentType": FLOAT,
+                        "count": nb_vertices,
+                        "type": "VEC3",
+                        "min": min,
+                        "max": max,
+                    },
+                    {
+                        "bufferView": 1,
+                        "componentType": FLOAT,
+                        "count": nb_vertices,
+                        "type": "VEC3",
+                    },
+                    {
+                        "bufferView": 2,
+                        "componentType": UNSIGNED_BYTE,
+                        "normalized": true,
+                        "count": nb_vertices,
+                        "type": "VEC4",
+                    },
+                    {
+                        "bufferView": 3,
+                        "componentType": UNSIGNED_INT,
+                        "count": mesh.indices.len(),
+                        "type": "SCALAR",
+                    },
+                ],
+                "bufferViews": views,
+                "buffers": [{ "byteLength": bin.len() }],
+            })
+        };
+
+        // Both chunks must be padded to 4 bytes: JSON with spaces, binary
+        // data with zeroes.
+        let mut json = gltf.to_string().into_bytes();
+        while json.len() % 4 != 0 {
+            json.push(b' ');
+        }
+        while bin.len() % 4 != 0 {
+            bin.push(0);
+        }
+
+        let bin_chunk_len = if bin.is_empty() { 0 } else { 8 + bin.len() };
+        let total_len = 12 + 8 + json.len() + bin_chunk_len;
+        let mut out = Vec::with_capacity(total_len);
+        out.extend_from_slice(b"glTF");
+        out.extend_from_slice(&2u32.to_le_bytes());
+        out.extend_from_slice(&(total_len as u32).to_le_bytes());
+        out.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        out.extend_from_slice(b"JSON");
+        out.extend_from_slice(&json);
+        if !bin.is_empty() {
+            out.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+            out.extend_from_slice(b"BIN\0");
+            out.extend_from_slice(&bin);
+        }
+
+        out
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::VoxelPalette;
+    use std::collections::HashMap;
+
+    fn single_voxel() -> VoxelModel {
+        let mut model = VoxelModel {
+            chunks: HashMap::new(),
+            palette: VoxelPalette::from_vox(&[0xff00_00ff]),
+        };
+        model.set(&(0, 0, 0).into(), 1);
+
+        model
+    }
+
+    #[test]
+    fn to_obj_single_voxel() {
+        let mut model = single_voxel();
+        model.remesh();
+
+        // One red quad per side, -X first, wound counter-clockwise as seen
+        // from the outside.
+        let expected = concat!(
+            "# 12 triangles\n",
+            "v 0 0 0 1 0 0\n",
+            "v 0 0 1 1 0 0\n",
+            "v 0 1 1 1 0 0\n",
+            "v 0 1 0 1 0 0\n",
+            "v 1 0 0 1 0 0\n",
+            "v 1 1 0 1 0 0\n",
+            "v 1 1 1 1 0 0\n",
+            "v 1 0 1 1 0 0\n",
+            "v 0 0 0 1 0 0\n",
+            "v 1 0 0 1 0 0\n",
+            "v 1 0 1 1 0 0\n",
+            "v 0 0 1 1 0 0\n",
+            "v 0 1 0 1 0 0\n",
+            "v 0 1 1 1 0 0\n",
+            "v 1 1 1 1 0 0\n",
+            "v 1 1 0 1 0 0\n",
+            "v 0 0 0 1 0 0\n",
+            "v 0 1 0 1 0 0\n",
+            "v 1 1 0 1 0 0\n",
+            "v 1 0 0 1 0 0\n",
+            "v 0 0 1 1 0 0\n",
+            "v 1 0 1 1 0 0\n",
+            "v 1 1 1 1 0 0\n",
+            "v 0 1 1 1 0 0\n",
+            "vn -1 0 0\n",
+            "vn -1 0 0\n",
+            "vn -1 0 0\n",
+            "vn -1 0 0\n",
+            "vn 1 0 0\n",
+            "vn 1 0 0\n",
+            "vn 1 0 0\n",
+            "vn 1 0 0\n",
+            "vn 0 -1 0\n",
+            "vn 0 -1 0\n",
+            "vn 0 -1 0\n",
+            "vn 0 -1 0\n",
+            "vn 0 1 0\n",
+            "vn 0 1 0\n",
+            "vn 0 1 0\n",
+            "vn 0 1 0\n",
+            "vn 0 0 -1\n",
+            "vn 0 0 -1\n",
+            "vn 0 0 -1\n",
+            "vn 0 0 -1\n",
+            "vn 0 0 1\n",
+            "vn 0 0 1\n",
+            "vn 0 0 1\n",
+            "vn 0 0 1\n",
+            "f 1//1 2//2 3//3\n",
+            "f 1//1 3//3 4//4\n",
+            "f 5//5 6//6 7//7\n",
+            "f 5//5 7//7 8//8\n",
+            "f 9//9 10//10 11//11\n",
+            "f 9//9 11//11 12//12\n",
+            "f 13//13 14//14 15//15\n",
+            "f 13//13 15//15 16//16\n",
+            "f 17//17 18//18 19//19\n",
+            "f 17//17 19//19 20//20\n",
+            "f 21//21 22//22 23//23\n",
+            "f 21//21 23//23 24//24\n",
+        );
+        assert_eq!(expected, model.to_obj());
+    }
+
+    #[test]
+    fn to_obj_meshes_dirty_chunks() {
+        let dirty = single_voxel();
+        let mut meshed = dirty.clone();
+        meshed.remesh();
+
+        assert!(dirty.meshes().next().is_none());
+        assert_eq!(meshed.to_obj(), dirty.to_obj());
+    }
+
+    #[test]
+    fn to_ply_header() {
+        let mut model = single_voxel();
+        model.remesh();
+        let ply = model.to_ply();
+
+        let mut lines = ply.lines();
+        let header: Vec<_> = lines.by_ref().take_while(|&l| l != "end_header").collect();
+        assert_eq!(
+            vec![
+                "ply",
+                "format ascii 1.0",
+                "element vertex 24",
+                "property float x",
+                "property float y",
+                "property float z",
+                "property float nx",
+                "property float ny",
+                "property float nz",
+                "property uchar red",
+                "property uchar green",
+                "property uchar blue",
+                "property uchar alpha",
+                "element face 12",
+                "property list uchar uint vertex_indices",
+            ],
+            header
+        );
+
+        let body: Vec<_> = lines.collect();
+        assert_eq!(24 + 12, body.len());
+        assert_eq!("0 0 0 -1 0 0 255 0 0 255", body[0]);
+        assert_eq!("3 0 1 2", body[24]);
+
+        let empty = VoxelModel::default().to_ply();
+        assert!(empty.contains("element vertex 0\n"));
+        assert!(empty.contains("element face 0\n"));
+        assert!(empty.ends_with("end_header\n"));
+    }
+
+    /// Checks the framing of a `.glb` file, and returns its JSON chunk along
+    /// with the length of its BIN chunk, if any.
+    fn glb_chunks(glb: &[u8]) -> (serde_json::Value, Option<usize>) {
+        let u32_at =
+            |i: usize| u32::from_le_bytes([glb[i], glb[i + 1], glb[i + 2], glb[i + 3]]) as usize;
+
+        assert_eq!(b"glTF", &glb[..4]);
+        assert_eq!(2, u32_at(4));
+        assert_eq!(glb.len(), u32_at(8));
+
+        let json_len = u32_at(12);
+        assert_eq!(0, json_len % 4);
+        assert_eq!(b"JSON", &glb[16..20]);
+        let json = serde_json::from_slice(&glb[20..20 + json_len]).unwrap();
+
+        let bin_start = 20 + json_len;
+        if bin_start == glb.len() {
+            return (json, None);
+        }
+        let bin_len = u32_at(bin_start);
+        assert_eq!(0, bin_len % 4);
+        assert_eq!(b"BIN\0", &glb[bin_start + 4..bin_start + 8]);
+        assert_eq!(glb.len(), bin_start + 8 + bin_len);
+
+        (json, Some(bin_len))
+    }
+
+    #[test]
+    fn to_glb_single_voxel() {
+        let mut model = single_voxel();
+        model.remesh();
+        let (json, bin_len) = glb_chunks(&model.to_glb());
+
+        // 24 positions & normals, 24 colors, 36 indices.
+        let expected = 24 * 12 * 2 + 24 * 4 + 36 * 4;
+        assert_eq!(Some(expected), bin_len);
+        assert_eq!(expected as u64, json["buffers"][0]["byteLength"]);
+
+        let views = json["bufferViews"].as_array().unwrap();
+        let lens: Vec<_> = views
+            .iter()
+            .map(|v| v["byteLength"].as_u64().unwrap())
+            .collect();
+        assert_eq!(vec![288, 288, 96, 144], lens);
+        let end = views
+            .last()
+            .map(|v| v["byteOffset"].as_u64().unwrap() + 144);
+        assert_eq!(Some(expected as u64), end);
+
+        assert_eq!(24, json["accessors"][0]["count"]);
+        assert_eq!(36, json["accessors"][3]["count"]);
+    }
+
+    #[test]
+    fn to_glb_empty() {
+        let (json, bin_len) = glb_chunks(&VoxelModel::default().to_glb());
+        assert_eq!(None, bin_len);
+        assert!(json.get("buffers").is_none());
+        assert_eq!(0, json["scenes"][0]["nodes"].as_array().unwrap().len());
+    }
+}
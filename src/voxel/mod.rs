@@ -0,0 +1,776 @@
+// TODO(cmc): rendering
+// TODO(cmc): collisions (DBVT of DBVTs)
+// TODO(cmc): chunk streaming
+// TODO(cmc): backface culling
+
+mod export;
+mod raycast;
+mod storage;
+mod vox;
+
+pub use raycast::VoxelHit;
+pub use vox::{VoxLayer, VoxRotation, VoxScene, VoxSceneModel};
+
+use crate::maths::prelude::*;
+use anyhow::{anyhow, Error as AnyError, Result as AnyResult};
+use std::{collections::HashMap, mem};
+use storage::VoxelStorage;
+
+// TODO(cmc): new types for world pos vs. local pos?
+
+// VoxelChunk are identified by the coordinates of their lower-left corner.
+//
+// Each voxel holds an index into the palette of its model, 0 meaning the voxel
+// is empty.
+#[derive(Clone)]
+struct VoxelChunk {
+    // world_pos: Vec3i,
+    voxels: VoxelStorage,
+    // Number of non-empty voxels; a chunk that drops to zero gets deallocated.
+    nb_voxels: usize,
+    // `None` means the chunk has changed since it was last meshed.
+    mesh: Option<VoxelMesh>,
+}
+
+impl VoxelChunk {
+    // TODO(cmc): const generics
+    pub const SIZE: usize = 32;
+}
+
+impl Default for VoxelChunk {
+    fn default() -> Self {
+        Self {
+            voxels: VoxelStorage::default(),
+            nb_voxels: 0,
+            mesh: None,
+        }
+    }
+}
+
+impl VoxelChunk {
+    pub fn stats(&self /* , passes: &[OptimizationPass] */) -> VoxelModelStats {
+        let nb_triangles = self.mesh.as_ref().map_or(0, |m| m.nb_triangles());
+        let nb_bytes = mem::size_of::<Self>()
+            + self.voxels.heap_size()
+            + self.mesh.as_ref().map_or(0, |m| m.heap_size());
+        VoxelModelStats {
+            nb_chunks: 1,
+            nb_voxels: self.nb_voxels,
+            nb_triangles,
+            nb_bytes,
+        }
+    }
+
+    pub fn iter_local(&self) -> impl Iterator<Item = (Vec3i, u8)> + '_ {
+        let (mut x, mut y, mut z) = (0i32, 0i32, 0i32);
+        (0..Self::SIZE.pow(3)).map(move |i| {
+            let v = self.voxels[i];
+            let res = ((x, y, z).into(), v);
+
+            x += 1;
+            if x >= Self::SIZE as i32 {
+                x = 0;
+                y += 1;
+            }
+            if y >= Self::SIZE as i32 {
+                x = 0;
+                y = 0;
+                z += 1;
+            }
+
+            res
+        })
+    }
+
+    pub fn iter_world(&self, world_pos: &Vec3i) -> impl Iterator<Item = (Vec3i, u8)> + '_ {
+        let world_pos = **world_pos;
+        self.iter_local()
+            .map(move |(pos, v)| ((*pos + world_pos).into(), v))
+    }
+}
+
+impl VoxelChunk {
+    fn contains_local(pos: [i32; 3]) -> bool {
+        pos.iter().all(|&c| c >= 0 && c < Self::SIZE as i32)
+    }
+
+    fn local_index(pos: [i32; 3]) -> usize {
+        let size = Self::SIZE as i32;
+        (pos[0] + pos[1] * size + pos[2] * size * size) as usize
+    }
+
+    /// Greedily merges the visible faces of the chunk into as few quads as
+    /// possible, one axis-aligned slice at a time.
+    ///
+    /// `voxel_at` is queried with chunk-local coordinates, which go one voxel
+    /// past the chunk on each side so that faces hidden by a neighbouring
+    /// chunk can be culled too.
+    /// Only faces of the same color get merged together.
+    ///
+    /// See https://0fps.net/2012/06/30/meshing-in-a-minecraft-game/.
+    fn greedy_mesh(world_pos: &Vec3i, voxel_at: impl Fn([i32; 3]) -> u8) -> VoxelMesh {
+        const SIZE: usize = VoxelChunk::SIZE;
+
+        let mut mesh = VoxelMesh::default();
+        // Faces of the current slice, holding the palette index of the voxel
+        // they belong to: positive when facing towards +d, negative when
+        // facing towards -d, zero when there is no face.
+        let mut mask = vec![0i16; SIZE * SIZE];
+
+        for d in 0..3 {
+            let (u, v) = ((d + 1) % 3, (d + 2) % 3);
+
+            // Walk through the planes that separate slice `x[d]` from slice
+            // `x[d] + 1`, including both outer boundaries of the chunk.
+            for slice in -1..SIZE as i32 {
+                let mut n = 0;
+                for j in 0..SIZE {
+                    for i in 0..SIZE {
+                        let mut a = [0i32; 3];
+                        a[d] = slice;
+                        a[u] = i as i32;
+                        a[v] = j as i32;
+                        let mut b = a;
+                        b[d] += 1;
+
+                        // A face belongs to the voxel it bounds: the chunk
+                        // only emits the faces of its own voxels, no matter
+                        // where their neighbour lives.
+                        let (va, vb) = (voxel_at(a), voxel_at(b));
+                        mask[n] = if va != 0 && vb == 0 && Self::contains_local(a) {
+                            va as i16
+                        } else if vb != 0 && va == 0 && Self::contains_local(b) {
+                            -(vb as i16)
+                        } else {
+                            0
+                        };
+                        n += 1;
+                    }
+                }
+
+                let mut n = 0;
+                for j in 0..SIZE {
+                    let mut i = 0;
+                    while i < SIZE {
+                        let face = mask[n];
+                        if face == 0 {
+                            i += 1;
+                            n += 1;
+                            continue;
+                        }
+
+                        let mut w = 1;
+                        while i + w < SIZE && mask[n + w] == face {
+                            w += 1;
+                        }
+
+                        let mut h = 1;
+                        'grow: while j + h < SIZE {
+                            for k in 0..w {
+                                if mask[n + k + h * SIZE] != face {
+                                    break 'grow;
+                                }
+                            }
+                            h += 1;
+                        }
+
+                        let mut corner = [0f32; 3];
+                        corner[d] = (slice + 1) as f32;
+                        corner[u] = i as f32;
+                        corner[v] = j as f32;
+                        let corner: Vec3 = (
+                            corner[0] + world_pos.x as f32,
+                            corner[1] + world_pos.y as f32,
+                            corner[2] + world_pos.z as f32,
+                        )
+                            .into();
+
+                        let (mut du, mut dv, mut normal) = ([0f32; 3], [0f32; 3], [0f32; 3]);
+                        du[u] = w as f32;
+                        dv[v] = h as f32;
+                        normal[d] = face.signum() as f32;
+
+                        // (d, u, v) is a direct basis, hence going around du
+                        // then dv winds counter-clockwise as seen from +d.
+                        let color = face.abs() as u8;
+                        if face > 0 {
+                            mesh.push_quad(corner, du.into(), dv.into(), normal.into(), color);
+                        } else {
+                            mesh.push_quad(corner, dv.into(), du.into(), normal.into(), color);
+                        }
+
+                        for l in 0..h {
+                            for k in 0..w {
+                                mask[n + k + l * SIZE] = 0;
+                            }
+                        }
+                        i += w;
+                        n += w;
+                    }
+                }
+            }
+        }
+
+        mesh
+    }
+}
+
+impl VoxelChunk {
+    pub fn checkerboard() -> Self {
+        let mut vc = Self::default();
+        for i in (0..Self::SIZE.pow(3)).step_by(3) {
+            vc.voxels.set(i, 1);
+            vc.nb_voxels += 1;
+        }
+
+        vc
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+#[derive(Clone)]
+pub struct VoxelModel {
+    // TODO(cmc): dont use uberly secure hashing methods
+    // Chunks are boxed so that the spare capacity of the map doesn't cost a
+    // whole chunk per bucket.
+    chunks: HashMap<Vec3i, Box<VoxelChunk>>,
+    palette: VoxelPalette,
+}
+
+impl Default for VoxelModel {
+    fn default() -> Self {
+        let chunks = HashMap::with_capacity(8);
+        let palette = VoxelPalette::default();
+        Self { chunks, palette }
+    }
+}
+
+impl VoxelModel {
+    pub fn stats(&self /* , passes: &[OptimizationPass] */) -> VoxelModelStats {
+        let mut stats: VoxelModelStats =
+            self.chunks.iter().map(|(_, c)| c.stats(/* passes */)).sum();
+        stats.nb_bytes += mem::size_of::<Self>()
+            + self.chunks.capacity() * mem::size_of::<(Vec3i, Box<VoxelChunk>)>();
+
+        stats
+    }
+
+    pub fn palette(&self) -> &VoxelPalette {
+        &self.palette
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Vec3i, u8)> + '_ {
+        self.chunks
+            .iter()
+            .map(|(world_pos, c)| c.iter_world(world_pos))
+            .flatten()
+    }
+
+    /// Returns the inclusive bounds of the non-empty voxels of the model, or
+    /// `None` if the model is empty.
+    pub fn bounds(&self) -> Option<(Vec3i, Vec3i)> {
+        self.iter()
+            .filter(|&(_, v)| v != 0)
+            .fold(None, |bounds, (pos, _)| {
+                let (min, max) = bounds.unwrap_or((pos, pos));
+                let min = (min.x.min(pos.x), min.y.min(pos.y), min.z.min(pos.z));
+                let max = (max.x.max(pos.x), max.y.max(pos.y), max.z.max(pos.z));
+                Some((min.into(), max.into()))
+            })
+    }
+
+    /// Returns whether any chunk changed since the last call to `remesh`.
+    pub fn is_dirty(&self) -> bool {
+        self.chunks.values().any(|c| c.mesh.is_none())
+    }
+
+    /// Regenerates the meshes of every chunk that changed since the last
+    /// call; untouched chunks keep their cached mesh.
+    ///
+    /// The storage of the regenerated chunks gets compacted on the way.
+    pub fn remesh(&mut self) {
+        let dirty: Vec<Vec3i> = self
+            .chunks
+            .iter()
+            .filter(|(_, c)| c.mesh.is_none())
+            .map(|(&world_pos, _)| world_pos)
+            .collect();
+
+        for world_pos in dirty {
+            let mesh = self.mesh_chunk(&world_pos);
+            let chunk = self.chunks.get_mut(&world_pos).unwrap();
+            chunk.voxels.compact();
+            chunk.mesh = mesh.into();
+        }
+    }
+
+    fn mesh_chunk(&self, world_pos: &Vec3i) -> VoxelMesh {
+        let chunk = &self.chunks[world_pos];
+        VoxelChunk::greedy_mesh(world_pos, |local| {
+            if VoxelChunk::contains_local(local) {
+                return chunk.voxels[VoxelChunk::local_index(local)];
+            }
+
+            // Outside of the chunk: ask the neighbour, if there is one.
+            let pos: Vec3i = (
+                world_pos.x + local[0],
+                world_pos.y + local[1],
+                world_pos.z + local[2],
+            )
+                .into();
+            self.get(&pos).unwrap_or(0)
+        })
+    }
+
+    /// Returns the cached meshes of the model, as of the last `remesh`.
+    pub fn meshes(&self) -> impl Iterator<Item = &VoxelMesh> + '_ {
+        self.chunks.values().filter_map(|c| c.mesh.as_ref())
+    }
+}
+
+impl VoxelModel {
+    pub fn checkerboard() -> Self {
+        let mut chunks = HashMap::with_capacity(1);
+        chunks.insert((0, 0, 0).into(), Box::new(VoxelChunk::checkerboard()));
+
+        let palette = VoxelPalette::default();
+        let mut model = Self { chunks, palette };
+        model.remesh();
+
+        model
+    }
+
+    pub fn from_vox(data: &[u8]) -> AnyResult<Vec<VoxelModel>> {
+        let data = dot_vox::load_bytes(data).map_err(|msg| anyhow!("{}", msg))?;
+        let palette = VoxelPalette::from_vox(&data.palette);
+        let models = data
+            .models
+            .into_iter()
+            .map(|vox_model| Self::from_dot_vox(vox_model, &palette))
+            .collect();
+
+        Ok(models)
+    }
+
+    fn from_dot_vox(vox_model: dot_vox::Model, palette: &VoxelPalette) -> Self {
+        // Chunks only get allocated once a voxel lands in them.
+        let mut model = VoxelModel {
+            chunks: HashMap::new(),
+            palette: palette.clone(),
+        };
+
+        for voxel in vox_model.voxels {
+            let pos: Vec3i = (voxel.x as i32, voxel.y as i32, voxel.z as i32).into();
+            // dot_vox already shifted color indices down by one so that they
+            // point straight into its palette.
+            model.set(&pos, voxel.i + 1);
+        }
+        model.remesh();
+
+        model
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+use std::ops::Index;
+
+struct LocalPos(Vec3i);
+struct WorldPos(Vec3i);
+
+impl WorldPos {
+    /// Splits a world position into the position of the chunk it belongs to
+    /// and its local position within that chunk.
+    ///
+    /// Chunks are aligned on multiples of `VoxelChunk::SIZE`, negative
+    /// coordinates included, hence the floor (euclidean) division.
+    fn split(&self) -> (Vec3i, LocalPos) {
+        let size = VoxelChunk::SIZE as i32;
+        let pos = self.0;
+
+        let chunk_pos = (
+            pos.x.div_euclid(size) * size,
+            pos.y.div_euclid(size) * size,
+            pos.z.div_euclid(size) * size,
+        );
+        let local_pos = (
+            pos.x.rem_euclid(size),
+            pos.y.rem_euclid(size),
+            pos.z.rem_euclid(size),
+        );
+
+        (chunk_pos.into(), LocalPos(local_pos.into()))
+    }
+}
+
+impl LocalPos {
+    fn index(&self) -> usize {
+        VoxelChunk::local_index([self.0.x, self.0.y, self.0.z])
+    }
+}
+
+impl VoxelModel {
+    /// Returns the voxel at `pos`, or `None` if no chunk was ever allocated
+    /// there.
+    pub fn get(&self, pos: &Vec3i) -> Option<u8> {
+        let (chunk_pos, local_pos) = WorldPos(*pos).split();
+        self.chunks
+            .get(&chunk_pos)
+            .map(|c| c.voxels[local_pos.index()])
+    }
+
+    /// Sets the voxel at `pos`, allocating its chunk on the fly if needed.
+    ///
+    /// Chunks left empty by the edit are deallocated.
+    pub fn set(&mut self, pos: &Vec3i, voxel: u8) {
+        let (chunk_pos, local_pos) = WorldPos(*pos).split();
+        if voxel == 0 && !self.chunks.contains_key(&chunk_pos) {
+            return;
+        }
+
+        let chunk = self.chunks.entry(chunk_pos).or_default();
+        let prev = chunk.voxels.get(local_pos.index());
+        if prev == voxel {
+            return;
+        }
+        match (prev, voxel) {
+            (0, _) => chunk.nb_voxels += 1,
+            (_, 0) => chunk.nb_voxels -= 1,
+            _ => {}
+        }
+        chunk.voxels.set(local_pos.index(), voxel);
+        chunk.mesh = None;
+
+        if chunk.nb_voxels == 0 {
+            self.chunks.remove(&chunk_pos);
+        }
+
+        self.invalidate_neighbours(&chunk_pos, &local_pos);
+    }
+
+    /// A voxel sitting on the boundary of its chunk might hide or reveal a
+    /// face of the neighbouring chunks.
+    fn invalidate_neighbours(&mut self, chunk_pos: &Vec3i, local_pos: &LocalPos) {
+        let size = VoxelChunk::SIZE as i32;
+        for axis in 0..3 {
+            let mut offset: Vec3i = (0, 0, 0).into();
+            if local_pos.0[axis] == 0 {
+                offset[axis] = -size;
+            } else if local_pos.0[axis] == size - 1 {
+                offset[axis] = size;
+            } else {
+                continue;
+            }
+            if let Some(neighbour) = self.chunks.get_mut(&(**chunk_pos + *offset).into()) {
+                neighbour.mesh = None;
+            }
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+use std::fmt;
+
+impl fmt::Debug for VoxelModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stats = self.stats();
+        f.debug_struct("VoxelModel")
+            .field("nb_chunks", &stats.nb_chunks)
+            .field("nb_voxels", &stats.nb_voxels)
+            .field("nb_triangles", &stats.nb_triangles)
+            .field("nb_bytes", &stats.nb_bytes)
+            .finish()
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rgba {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl From<u32> for Rgba {
+    /// Expects the layout used by MagicaVoxel & dot_vox, i.e. `0xAABBGGRR`.
+    fn from(c: u32) -> Self {
+        Self {
+            r: c as u8,
+            g: (c >> 8) as u8,
+            b: (c >> 16) as u8,
+            a: (c >> 24) as u8,
+        }
+    }
+}
+
+/// Maps the value of a voxel to its color, MagicaVoxel style: 0 is the empty
+/// voxel, 1 through 255 are actual colors.
+#[derive(Clone)]
+pub struct VoxelPalette([Rgba; 256]);
+
+impl Default for VoxelPalette {
+    fn default() -> Self {
+        Self::from_vox(&dot_vox::DEFAULT_PALETTE[..])
+    }
+}
+
+impl VoxelPalette {
+    /// Builds a palette from a dot_vox one, where the color of the voxel `i`
+    /// lives at `i - 1`.
+    pub fn from_vox(palette: &[u32]) -> Self {
+        let mut colors = [Rgba::default(); 256];
+        colors
+            .iter_mut()
+            .skip(1)
+            .zip(palette.iter())
+            .for_each(|(c, &vox_c)| *c = vox_c.into());
+
+        Self(colors)
+    }
+}
+
+impl Index<u8> for VoxelPalette {
+    type Output = Rgba;
+
+    fn index(&self, voxel: u8) -> &Self::Output {
+        &self.0[voxel as usize]
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// An indexed triangle mesh, in model space.
+///
+/// Vertex colors are indices into the palette of the model the mesh was
+/// generated from.
+#[derive(Debug, Clone, Default)]
+pub struct VoxelMesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub colors: Vec<u8>,
+    pub indices: Vec<u32>,
+}
+
+impl VoxelMesh {
+    pub fn nb_triangles(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// Memory held by the mesh on the heap, in bytes.
+    pub fn heap_size(&self) -> usize {
+        self.positions.capacity() * mem::size_of::<Vec3>()
+            + self.normals.capacity() * mem::size_of::<Vec3>()
+            + self.colors.capacity() * mem::size_of::<u8>()
+            + self.indices.capacity() * mem::size_of::<u32>()
+    }
+
+    pub fn triangles(&self) -> impl Iterator<Item = [u32; 3]> + '_ {
+        self.indices.chunks(3).map(|t| [t[0], t[1], t[2]])
+    }
+
+    // Expects `du` then `dv` to wind counter-clockwise around `normal`.
+    fn push_quad(&mut self, corner: Vec3, du: Vec3, dv: Vec3, normal: Vec3, color: u8) {
+        let base = self.positions.len() as u32;
+        self.positions.extend_from_slice(&[
+            corner,
+            (*corner + *du).into(),
+            (*corner + *du + *dv).into(),
+            (*corner + *dv).into(),
+        ]);
+        self.normals.extend_from_slice(&[normal; 4]);
+        self.colors.extend_from_slice(&[color; 4]);
+        self.indices
+            .extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Default)]
+pub struct VoxelModelStats {
+    pub nb_chunks: usize,
+    pub nb_voxels: usize,
+    pub nb_triangles: usize,
+    /// Approximate memory footprint, in bytes, meshes included.
+    pub nb_bytes: usize,
+}
+
+use std::ops::AddAssign;
+impl AddAssign for VoxelModelStats {
+    fn add_assign(&mut self, other: Self) {
+        *self = Self {
+            nb_chunks: self.nb_chunks + other.nb_chunks,
+            nb_voxels: self.nb_voxels + other.nb_voxels,
+            nb_triangles: self.nb_triangles + other.nb_triangles,
+            nb_bytes: self.nb_bytes + other.nb_bytes,
+        }
+    }
+}
+
+use std::iter::Sum;
+impl Sum<Self> for VoxelModelStats {
+    fn sum<I>(iter: I) -> Self
+    where
+        I: Iterator<Item = Self>,
+    {
+        let mut stats = Self::default();
+        iter.for_each(|s| stats += s);
+
+        stats
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn filled(min: Vec3i, max: Vec3i) -> VoxelModel {
+        let mut model = VoxelModel::default();
+        for z in min.z..max.z {
+            for y in min.y..max.y {
+                for x in min.x..max.x {
+                    model.set(&(x, y, z).into(), 1);
+                }
+            }
+        }
+        model.remesh();
+
+        model
+    }
+
+    // Every face is a quad, i.e. two triangles.
+    fn nb_faces(model: &VoxelModel) -> usize {
+        model.stats().nb_triangles / 2
+    }
+
+    #[test]
+    fn greedy_mesh_solid_chunk() {
+        let size = VoxelChunk::SIZE as i32;
+        let model = filled((0, 0, 0).into(), (size, size, size).into());
+
+        assert_eq!(1, model.stats().nb_chunks);
+        assert_eq!(6, nb_faces(&model));
+    }
+
+    #[test]
+    fn greedy_mesh_slab() {
+        let size = VoxelChunk::SIZE as i32;
+        let model = filled((0, 0, 0).into(), (size * 2, size, size).into());
+
+        // Each chunk keeps its 6 faces but the one it shares with the other.
+        assert_eq!(2, model.stats().nb_chunks);
+        assert_eq!(10, nb_faces(&model));
+
+        let shared = model.meshes().any(|mesh| {
+            mesh.positions
+                .iter()
+                .zip(&mesh.normals)
+                .any(|(pos, normal)| {
+                    normal.x != 0. && (pos.x - size as f32).abs() < std::f32::EPSILON
+                })
+        });
+        assert!(!shared, "the face shared by both chunks must be culled");
+    }
+
+    #[test]
+    fn greedy_mesh_checkerboard() {
+        let model = VoxelModel::checkerboard();
+
+        // No two voxels share a face, nor do any two coplanar faces share an
+        // edge: nothing gets culled, nothing gets merged.
+        let nb_voxels = (VoxelChunk::SIZE.pow(3) + 2) / 3;
+        assert_eq!(nb_voxels, model.stats().nb_voxels);
+        assert_eq!(6 * nb_voxels, nb_faces(&model));
+    }
+
+    #[test]
+    fn dirty_until_remeshed() {
+        let mut model = VoxelModel::default();
+        assert!(!model.is_dirty());
+
+        model.set(&(0, 0, 0).into(), 1);
+        assert!(model.is_dirty());
+        model.remesh();
+        assert!(!model.is_dirty());
+
+        // Only the chunk that was touched needs meshing again.
+        model.set(&(40, 0, 0).into(), 1);
+        assert!(model.is_dirty());
+        model.remesh();
+        assert!(!model.is_dirty());
+    }
+
+    #[test]
+    fn world_pos_split() {
+        let size = VoxelChunk::SIZE as i32;
+        let split = |pos: (i32, i32, i32)| {
+            let (chunk_pos, local_pos) = WorldPos(pos.into()).split();
+            (*chunk_pos, *local_pos.0)
+        };
+
+        assert_eq!(((0, 0, 0).into(), (0, 0, 0).into()), split((0, 0, 0)));
+        assert_eq!(
+            ((-size, 0, size).into(), (size - 1, 5, 0).into()),
+            split((-1, 5, size))
+        );
+        assert_eq!(
+            ((-2 * size, -size, 0).into(), (size - 1, 0, 1).into()),
+            split((-size - 1, -size, 1))
+        );
+    }
+
+    #[test]
+    fn get_set_against_reference() {
+        let mut rng = StdRng::seed_from_u64(42);
+
+        for _ in 0..16 {
+            let mut model = VoxelModel::default();
+            let mut reference: HashMap<Vec3i, u8> = HashMap::new();
+
+            // A small range makes for plenty of overwrites & clears, spread
+            // over chunks on both sides of the origin.
+            let random_pos = |rng: &mut StdRng| -> Vec3i {
+                (
+                    rng.gen_range(-40, 40),
+                    rng.gen_range(-40, 40),
+                    rng.gen_range(-40, 40),
+                )
+                    .into()
+            };
+
+            for _ in 0..2000 {
+                let pos = random_pos(&mut rng);
+                let voxel = rng.gen_range(0, 4);
+                model.set(&pos, voxel);
+                reference.insert(pos, voxel);
+
+                let pos = random_pos(&mut rng);
+                assert_eq!(
+                    reference.get(&pos).copied().unwrap_or(0),
+                    model.get(&pos).unwrap_or(0),
+                    "mismatch at {:?}",
+                    pos
+                );
+            }
+
+            for (pos, &voxel) in &reference {
+                assert_eq!(voxel, model.get(pos).unwrap_or(0), "mismatch at {:?}", pos);
+            }
+            let nb_voxels = reference.values().filter(|&&v| v != 0).count();
+            assert_eq!(nb_voxels, model.stats().nb_voxels);
+            assert_eq!(nb_voxels, model.iter().filter(|&(_, v)| v != 0).count());
+
+            for pos in reference.keys() {
+                model.set(pos, 0);
+            }
+            assert!(model.chunks.is_empty());
+        }
+    }
+}
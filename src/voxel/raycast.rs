@@ -0,0 +1,251 @@
+use super::{VoxelChunk, VoxelModel};
+use crate::maths::prelude::*;
+use collision::Ray3;
+
+// -----------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoxelHit {
+    /// World position of the voxel that was hit.
+    pub pos: Vec3i,
+    /// Normal of the face the ray entered through; zero if the ray started
+    /// inside of the voxel.
+    pub normal: Vec3i,
+    /// Distance from the origin of the ray to the hit point.
+    pub distance: f32,
+    pub voxel: u8,
+}
+
+impl VoxelModel {
+    /// Walks the voxels along `ray` (Amanatides & Woo) and returns the first
+    /// non-empty one, if any within `max_distance`.
+    ///
+    /// `offset` is the world position of the model (i.e. its `GridPosition`);
+    /// both the ray and the hit live in world space.
+    pub fn raycast(&self, offset: &Vec3i, ray: &Ray3<f32>, max_distance: f32) -> Option<VoxelHit> {
+        let dir = ray.direction.normalize();
+        let origin = [
+            ray.origin.x - offset.x as f32,
+            ray.origin.y - offset.y as f32,
+            ray.origin.z - offset.z as f32,
+        ];
+        let dir = [dir.x, dir.y, dir.z];
+
+        // Clip the ray against the allocated chunks first, so that the walk
+        // never wanders off into the void.
+        let (min, max) = self.chunks.keys().fold(None, |bounds, pos| {
+            let (min, max) = bounds.unwrap_or(([pos.x, pos.y, pos.z], [pos.x, pos.y, pos.z]));
+            Some((
+                [min[0].min(pos.x), min[1].min(pos.y), min[2].min(pos.z)],
+                [max[0].max(pos.x), max[1].max(pos.y), max[2].max(pos.z)],
+            ))
+        })?;
+        let size = VoxelChunk::SIZE as i32;
+        let max = [max[0] + size, max[1] + size, max[2] + size];
+
+        let (mut t_enter, mut t_exit) = (0.0f32, max_distance);
+        let mut enter_axis = None;
+        for axis in 0..3 {
+            if dir[axis] == 0.0 {
+                if origin[axis] < min[axis] as f32 || origin[axis] >= max[axis] as f32 {
+                    return None;
+                }
+                continue;
+            }
+            let t1 = (min[axis] as f32 - origin[axis]) / dir[axis];
+            let t2 = (max[axis] as f32 - origin[axis]) / dir[axis];
+            let (t1, t2) = (t1.min(t2), t1.max(t2));
+            if t1 > t_enter {
+                t_enter = t1;
+                enter_axis = Some(axis);
+            }
+            t_exit = t_exit.min(t2);
+        }
+        if t_enter > t_exit {
+            return None;
+        }
+
+        let mut pos = [0i32; 3];
+        let mut step = [0i32; 3];
+        let mut t_max = [std::f32::INFINITY; 3];
+        let mut t_delta = [std::f32::INFINITY; 3];
+        for axis in 0..3 {
+            let p = origin[axis] + dir[axis] * t_enter;
+            // Floating point errors can land the entry point right outside of
+            // the bounds.
+            pos[axis] = (p.floor() as i32).max(min[axis]).min(max[axis] - 1);
+
+            if dir[axis] > 0.0 {
+                step[axis] = 1;
+                t_max[axis] = ((pos[axis] + 1) as f32 - origin[axis]) / dir[axis];
+                t_delta[axis] = 1.0 / dir[axis];
+            } else if dir[axis] < 0.0 {
+                step[axis] = -1;
+                t_max[axis] = (pos[axis] as f32 - origin[axis]) / dir[axis];
+                t_delta[axis] = -1.0 / dir[axis];
+            }
+        }
+
+        let mut normal = [0i32; 3];
+        if let Some(axis) = enter_axis {
+            normal[axis] = -step[axis];
+        }
+
+        let mut t = t_enter;
+        while t <= t_exit {
+            let voxel = self.get(&pos.into()).unwrap_or(0);
+            if voxel != 0 {
+                return Some(VoxelHit {
+                    pos: (pos[0] + offset.x, pos[1] + offset.y, pos[2] + offset.z).into(),
+                    normal: normal.into(),
+                    distance: t,
+                    voxel,
+                });
+            }
+
+            let axis = if t_max[0] < t_max[1] {
+                if t_max[0] < t_max[2] {
+                    0
+                } else {
+                    2
+                }
+            } else if t_max[1] < t_max[2] {
+                1
+            } else {
+                2
+            };
+
+            t = t_max[axis];
+            t_max[axis] += t_delta[axis];
+            pos[axis] += step[axis];
+            normal = [0; 3];
+            normal[axis] = -step[axis];
+        }
+
+        None
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Point3 as CGPoint3, Vector3 as CGVec3};
+
+    fn model(voxels: &[(i32, i32, i32)]) -> VoxelModel {
+        let mut model = VoxelModel::default();
+        voxels.iter().for_each(|&pos| model.set(&pos.into(), 1));
+
+        model
+    }
+
+    fn ray(origin: (f32, f32, f32), dir: (f32, f32, f32)) -> Ray3<f32> {
+        Ray3::new(CGPoint3::from(origin), CGVec3::from(dir))
+    }
+
+    fn hit(pos: (i32, i32, i32), normal: (i32, i32, i32), distance: f32) -> Option<VoxelHit> {
+        Some(VoxelHit {
+            pos: pos.into(),
+            normal: normal.into(),
+            distance,
+            voxel: 1,
+        })
+    }
+
+    #[test]
+    fn single_chunk() {
+        let model = model(&[(5, 5, 5)]);
+        let zero = (0, 0, 0).into();
+
+        // From within the bounds of the chunk...
+        let r = ray((0.5, 5.5, 5.5), (1., 0., 0.));
+        assert_eq!(
+            hit((5, 5, 5), (-1, 0, 0), 4.5),
+            model.raycast(&zero, &r, 100.)
+        );
+        // ...and from outside of them.
+        let r = ray((-10.5, 5.5, 5.5), (1., 0., 0.));
+        assert_eq!(
+            hit((5, 5, 5), (-1, 0, 0), 15.5),
+            model.raycast(&zero, &r, 100.)
+        );
+
+        // Every axis, both ways.
+        let r = ray((5.5, 5.5, 20.), (0., 0., -2.));
+        assert_eq!(
+            hit((5, 5, 5), (0, 0, 1), 14.),
+            model.raycast(&zero, &r, 100.)
+        );
+        let r = ray((5.5, 1., 5.5), (0., 1., 0.));
+        assert_eq!(
+            hit((5, 5, 5), (0, -1, 0), 4.),
+            model.raycast(&zero, &r, 100.)
+        );
+    }
+
+    #[test]
+    fn across_chunks() {
+        // Chunks at -64 and 32 on X, with the unallocated -32 one in between
+        // for good measure.
+        let model = model(&[(-40, 3, 3), (20, 20, 20), (35, 3, 3)]);
+        let zero = (0, 0, 0).into();
+
+        let r = ray((10.5, 3.5, 3.5), (-1., 0., 0.));
+        assert_eq!(
+            hit((-40, 3, 3), (1, 0, 0), 49.5),
+            model.raycast(&zero, &r, 100.)
+        );
+        let r = ray((-10.5, 3.5, 3.5), (1., 0., 0.));
+        assert_eq!(
+            hit((35, 3, 3), (-1, 0, 0), 45.5),
+            model.raycast(&zero, &r, 100.)
+        );
+    }
+
+    #[test]
+    fn with_offset() {
+        let model = model(&[(1, 2, 3)]);
+        let offset = (100, 0, -50).into();
+
+        let r = ray((101.5, 10., -46.5), (0., -1., 0.));
+        assert_eq!(
+            hit((101, 2, -47), (0, 1, 0), 7.),
+            model.raycast(&offset, &r, 100.)
+        );
+        // Where the voxel would be without the offset.
+        let r = ray((1.5, 10., 3.5), (0., -1., 0.));
+        assert_eq!(None, model.raycast(&offset, &r, 100.));
+    }
+
+    #[test]
+    fn from_inside() {
+        let model = model(&[(5, 5, 5)]);
+
+        let r = ray((5.5, 5.5, 5.5), (1., 1., 0.));
+        let hit = hit((5, 5, 5), (0, 0, 0), 0.);
+        assert_eq!(hit, model.raycast(&(0, 0, 0).into(), &r, 100.));
+    }
+
+    #[test]
+    fn misses() {
+        let model = model(&[(5, 5, 5)]);
+        let zero = (0, 0, 0).into();
+
+        // Too far away.
+        let r = ray((0.5, 5.5, 5.5), (1., 0., 0.));
+        assert_eq!(None, model.raycast(&zero, &r, 4.));
+        // Right next to it.
+        let r = ray((0.5, 6.5, 5.5), (1., 0., 0.));
+        assert_eq!(None, model.raycast(&zero, &r, 100.));
+        // The other way.
+        let r = ray((0.5, 5.5, 5.5), (-1., 0., 0.));
+        assert_eq!(None, model.raycast(&zero, &r, 100.));
+        // Parallel to, and outside of, the chunk.
+        let r = ray((0.5, 40., 5.5), (1., 0., 0.));
+        assert_eq!(None, model.raycast(&zero, &r, 100.));
+        // Nothing to hit at all.
+        let r = ray((0.5, 5.5, 5.5), (1., 0., 0.));
+        assert_eq!(None, VoxelModel::default().raycast(&zero, &r, 100.));
+    }
+}
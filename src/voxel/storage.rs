@@ -0,0 +1,329 @@
+use super::VoxelChunk;
+use std::{mem, ops::Index};
+
+// -----------------------------------------------------------------------------
+
+const NB_VOXELS: usize = VoxelChunk::SIZE * VoxelChunk::SIZE * VoxelChunk::SIZE;
+const WORD_BITS: usize = 64;
+
+/// The voxels of a chunk, in whichever representation fits them best.
+///
+/// Edits upgrade the representation as needed; `compact` brings it back down
+/// to the smallest one that can hold the current voxels.
+#[derive(Clone, Debug)]
+pub enum VoxelStorage {
+    /// Every voxel holds the same value.
+    Uniform(u8),
+    /// One bit per voxel: set voxels all hold `value`, the others are empty.
+    Bits { value: u8, words: Vec<u64> },
+    /// Indices into `palette`, packed on `bits` bits per voxel.
+    ///
+    /// `bits` is always a power of two so that voxels never straddle two
+    /// words.
+    Palette {
+        palette: Vec<u8>,
+        bits: usize,
+        words: Vec<u64>,
+    },
+}
+
+impl Default for VoxelStorage {
+    fn default() -> Self {
+        Self::Uniform(0)
+    }
+}
+
+impl VoxelStorage {
+    fn nb_words(bits: usize) -> usize {
+        NB_VOXELS * bits / WORD_BITS
+    }
+
+    fn read(words: &[u64], bits: usize, i: usize) -> usize {
+        let (word, shift) = ((i * bits) / WORD_BITS, (i * bits) % WORD_BITS);
+        let mask = (1u64 << bits) - 1;
+        ((words[word] >> shift) & mask) as usize
+    }
+
+    fn write(words: &mut [u64], bits: usize, i: usize, v: usize) {
+        let (word, shift) = ((i * bits) / WORD_BITS, (i * bits) % WORD_BITS);
+        let mask = (1u64 << bits) - 1;
+        words[word] = (words[word] & !(mask << shift)) | ((v as u64 & mask) << shift);
+    }
+
+    fn pack(bits: usize, indices: impl Iterator<Item = usize>) -> Vec<u64> {
+        let mut words = vec![0; Self::nb_words(bits)];
+        indices
+            .enumerate()
+            .for_each(|(i, idx)| Self::write(&mut words, bits, i, idx));
+
+        words
+    }
+
+    fn bits_for(nb_values: usize) -> usize {
+        let mut bits = 1;
+        while (1 << bits) < nb_values {
+            bits *= 2;
+        }
+
+        bits
+    }
+}
+
+impl VoxelStorage {
+    pub fn get(&self, i: usize) -> u8 {
+        self[i]
+    }
+
+    /// Sets the `i`-th voxel, upgrading the representation if it cannot hold
+    /// the new value.
+    pub fn set(&mut self, i: usize, v: u8) {
+        let upgraded = match self {
+            Self::Uniform(u) if *u == v => return,
+            Self::Uniform(0) => Self::Bits {
+                value: v,
+                words: vec![0; Self::nb_words(1)],
+            },
+            Self::Uniform(u) if v == 0 => Self::Bits {
+                value: *u,
+                words: vec![!0; Self::nb_words(1)],
+            },
+            Self::Uniform(u) => Self::Palette {
+                palette: vec![*u],
+                bits: 1,
+                words: vec![0; Self::nb_words(1)],
+            },
+
+            Self::Bits { value, words } if v == 0 || v == *value => {
+                let (word, bit) = (i / WORD_BITS, i % WORD_BITS);
+                if v == 0 {
+                    words[word] &= !(1 << bit);
+                } else {
+                    words[word] |= 1 << bit;
+                }
+                return;
+            }
+            // An occupancy bitset is nothing but a 1-bit palette of
+            // [empty, value].
+            Self::Bits { value, words } => Self::Palette {
+                palette: vec![0, *value],
+                bits: 1,
+                words: mem::take(words),
+            },
+
+            Self::Palette {
+                palette,
+                bits,
+                words,
+            } => {
+                let idx = palette.iter().position(|&p| p == v).unwrap_or_else(|| {
+                    palette.push(v);
+                    palette.len() - 1
+                });
+
+                if idx >= 1 << *bits {
+                    let new_bits = *bits * 2;
+                    *words = Self::pack(
+                        new_bits,
+                        (0..NB_VOXELS).map(|i| Self::read(words, *bits, i)),
+                    );
+                    *bits = new_bits;
+                }
+
+                Self::write(words, *bits, i, idx);
+                return;
+            }
+        };
+
+        *self = upgraded;
+        self.set(i, v);
+    }
+
+    /// Switches to the smallest representation able to hold the current
+    /// voxels, dropping unused palette entries along the way.
+    pub fn compact(&mut self) {
+        if let Self::Uniform(_) = self {
+            return;
+        }
+
+        let mut used = [false; 256];
+        (0..NB_VOXELS).for_each(|i| used[self[i] as usize] = true);
+        let values: Vec<u8> = (0..=255u8).filter(|&v| used[v as usize]).collect();
+
+        let compacted = match values.as_slice() {
+            [v] => Self::Uniform(*v),
+            [0, value] => Self::Bits {
+                value: *value,
+                words: Self::pack(1, (0..NB_VOXELS).map(|i| (self[i] != 0) as usize)),
+            },
+            _ => {
+                let bits = Self::bits_for(values.len());
+                let mut lut = [0usize; 256];
+                values
+                    .iter()
+                    .enumerate()
+                    .for_each(|(idx, &v)| lut[v as usize] = idx);
+
+                Self::Palette {
+                    bits,
+                    words: Self::pack(bits, (0..NB_VOXELS).map(|i| lut[self[i] as usize])),
+                    palette: values,
+                }
+            }
+        };
+
+        *self = compacted;
+    }
+
+    /// Memory held by the storage on the heap, in bytes.
+    pub fn heap_size(&self) -> usize {
+        match self {
+            Self::Uniform(_) => 0,
+            Self::Bits { words, .. } => words.capacity() * mem::size_of::<u64>(),
+            Self::Palette { palette, words, .. } => {
+                palette.capacity() + words.capacity() * mem::size_of::<u64>()
+            }
+        }
+    }
+}
+
+impl Index<usize> for VoxelStorage {
+    type Output = u8;
+
+    fn index(&self, i: usize) -> &Self::Output {
+        const EMPTY: &u8 = &0;
+
+        match self {
+            Self::Uniform(v) => v,
+            Self::Bits { value, words } => {
+                if (words[i / WORD_BITS] >> (i % WORD_BITS)) & 1 == 1 {
+                    value
+                } else {
+                    EMPTY
+                }
+            }
+            Self::Palette {
+                palette,
+                bits,
+                words,
+            } => &palette[Self::read(words, *bits, i)],
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(storage: &VoxelStorage) -> Option<usize> {
+        match storage {
+            VoxelStorage::Uniform(_) => None,
+            VoxelStorage::Bits { .. } => Some(1),
+            VoxelStorage::Palette { bits, .. } => Some(*bits),
+        }
+    }
+
+    #[test]
+    fn uniform_to_bits_to_palette() {
+        let mut storage = VoxelStorage::default();
+        assert!(matches!(storage, VoxelStorage::Uniform(0)));
+
+        // Writing what's already there changes nothing.
+        storage.set(0, 0);
+        assert!(matches!(storage, VoxelStorage::Uniform(0)));
+
+        storage.set(0, 7);
+        storage.set(NB_VOXELS - 1, 7);
+        assert!(matches!(storage, VoxelStorage::Bits { value: 7, .. }));
+        storage.set(NB_VOXELS - 1, 0);
+        assert!(matches!(storage, VoxelStorage::Bits { value: 7, .. }));
+
+        // [0, 7] on 1 bit, which [0, 7, 8] immediately outgrows.
+        storage.set(1, 8);
+        assert!(matches!(storage, VoxelStorage::Palette { bits: 2, .. }));
+        assert_eq!((7, 8, 0), (storage[0], storage[1], storage[2]));
+    }
+
+    #[test]
+    fn uniform_non_empty() {
+        let mut storage = VoxelStorage::Uniform(3);
+        storage.set(5, 0);
+        assert!(matches!(storage, VoxelStorage::Bits { value: 3, .. }));
+        assert_eq!((3, 0, 3), (storage[4], storage[5], storage[6]));
+
+        let mut storage = VoxelStorage::Uniform(3);
+        storage.set(5, 4);
+        assert!(matches!(storage, VoxelStorage::Palette { bits: 1, .. }));
+        assert_eq!((3, 4, 3), (storage[4], storage[5], storage[6]));
+    }
+
+    #[test]
+    fn palette_grows() {
+        let mut storage = VoxelStorage::default();
+
+        // [0, 1] fit on 1 bit, [0, 1, 2] on 2, [0..=4] on 4, [0..=16] on 8.
+        let expected = [(1, 1), (2, 2), (4, 4), (16, 8)];
+        let mut prev = 0;
+        for &(v, expected_bits) in &expected {
+            for v in prev + 1..=v {
+                storage.set(v as usize * 100, v);
+            }
+            prev = v;
+            assert_eq!(Some(expected_bits), bits(&storage), "{} values", v + 1);
+        }
+
+        // Growing must not lose anything on the way.
+        for v in 1..=16u8 {
+            assert_eq!(v, storage[v as usize * 100]);
+        }
+        assert_eq!(0, storage[1]);
+        assert_eq!(0, storage[NB_VOXELS - 1]);
+    }
+
+    #[test]
+    fn compact_shrinks() {
+        let mut storage = VoxelStorage::default();
+        (1..=16u8).for_each(|v| storage.set(v as usize, v));
+        assert_eq!(Some(8), bits(&storage));
+
+        // Down to 4 values: 2 bits, palette pruned.
+        (4..=16u8).for_each(|v| storage.set(v as usize, 0));
+        storage.compact();
+        match &storage {
+            VoxelStorage::Palette { palette, bits, .. } => {
+                assert_eq!(&[0, 1, 2, 3], palette.as_slice());
+                assert_eq!(2, *bits);
+            }
+            storage => panic!("expected a palette, got {:?}", storage),
+        }
+        assert_eq!(
+            (1, 2, 3, 0),
+            (storage[1], storage[2], storage[3], storage[4])
+        );
+
+        // Down to empty & one value: bits.
+        (2..=3).for_each(|i| storage.set(i, 0));
+        storage.compact();
+        assert!(matches!(storage, VoxelStorage::Bits { value: 1, .. }));
+        assert_eq!((0, 1, 0), (storage[0], storage[1], storage[2]));
+
+        // Down to nothing at all: uniform.
+        storage.set(1, 0);
+        storage.compact();
+        assert!(matches!(storage, VoxelStorage::Uniform(0)));
+        assert_eq!(0, storage.heap_size());
+    }
+
+    #[test]
+    fn compact_full() {
+        let mut storage = VoxelStorage::default();
+        (0..NB_VOXELS).for_each(|i| storage.set(i, 9));
+        storage.set(0, 10);
+        storage.set(0, 9);
+        assert!(matches!(storage, VoxelStorage::Palette { .. }));
+
+        storage.compact();
+        assert!(matches!(storage, VoxelStorage::Uniform(9)));
+    }
+}
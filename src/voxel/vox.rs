@@ -0,0 +1,898 @@
+use super::{VoxelModel, VoxelPalette};
+use crate::maths::prelude::*;
+use anyhow::{anyhow, Result as AnyResult};
+use std::collections::HashMap;
+
+// -----------------------------------------------------------------------------
+
+// https://github.com/ephtracy/voxel-model/blob/master/MagicaVoxel-file-format-vox.txt
+// https://github.com/ephtracy/voxel-model/blob/master/MagicaVoxel-file-format-vox-extension.txt
+
+const VOX_VERSION: i32 = 150;
+/// Models cannot span more than 256 voxels in any direction, since their
+/// coordinates are stored on a single byte.
+const VOX_MAX_SIZE: i32 = 256;
+
+/// A rotation as encoded by MagicaVoxel, i.e. a signed permutation matrix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VoxRotation([[i32; 3]; 3]);
+
+impl Default for VoxRotation {
+    fn default() -> Self {
+        Self([[1, 0, 0], [0, 1, 0], [0, 0, 1]])
+    }
+}
+
+impl VoxRotation {
+    /// Decodes the packed `_r` attribute of a transform node:
+    /// bits 0-1 and 2-3 hold the column of the non-zero entry of the first
+    /// and second rows, bits 4 through 6 hold the sign of each row.
+    pub fn from_byte(r: u8) -> Option<Self> {
+        let (c0, c1) = ((r & 0b11) as usize, ((r >> 2) & 0b11) as usize);
+        if c0 > 2 || c1 > 2 || c0 == c1 {
+            return None;
+        }
+        let c2 = 3 - c0 - c1;
+        let sign = |bit: u8| if (r >> bit) & 1 == 1 { -1 } else { 1 };
+
+        let mut rows = [[0; 3]; 3];
+        rows[0][c0] = sign(4);
+        rows[1][c1] = sign(5);
+        rows[2][c2] = sign(6);
+
+        Some(Self(rows))
+    }
+
+    pub fn apply(&self, v: &Vec3i) -> Vec3i {
+        let m = &self.0;
+        (
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+            .into()
+    }
+
+    /// Rotates the voxel at `v`, i.e. the unit cell `[v, v + 1]`, around the
+    /// origin of the grid: its center lands on `R·(v + ½)`, hence a flipped
+    /// axis maps `v` onto `-v - 1` rather than `-v`.
+    pub fn apply_voxel(&self, v: &Vec3i) -> Vec3i {
+        let v = self.apply(v);
+        // Every row holds a single non-zero entry, which is negative iff the
+        // axis is flipped.
+        let flip = |row: &[i32; 3]| row.iter().sum::<i32>().min(0);
+        let m = &self.0;
+        (v.x + flip(&m[0]), v.y + flip(&m[1]), v.z + flip(&m[2])).into()
+    }
+
+    fn then(&self, inner: &Self) -> Self {
+        let (a, b) = (&self.0, &inner.0);
+        let mut rows = [[0; 3]; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                rows[i][j] = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+            }
+        }
+
+        Self(rows)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VoxLayer {
+    pub id: i32,
+    pub name: Option<String>,
+    pub hidden: bool,
+}
+
+/// A model placed within a MagicaVoxel scene.
+///
+/// A voxel at `pos` within `model` lives at
+/// `position + rotation.apply_voxel(pos)` in the scene.
+#[derive(Debug)]
+pub struct VoxSceneModel {
+    pub name: Option<String>,
+    pub model: VoxelModel,
+    pub position: Vec3i,
+    pub rotation: VoxRotation,
+    pub layer: Option<i32>,
+    pub hidden: bool,
+}
+
+#[derive(Debug)]
+pub struct VoxScene {
+    pub models: Vec<VoxSceneModel>,
+    pub layers: Vec<VoxLayer>,
+}
+
+impl VoxelModel {
+    /// Loads every model of a `.vox` file along with its placement in the
+    /// scene graph (transforms, groups & layers).
+    ///
+    /// Files that predate the scene graph get their models at the origin.
+    pub fn from_vox_scene(data: &[u8]) -> AnyResult<VoxScene> {
+        let vox = dot_vox::load_bytes(data).map_err(|msg| anyhow!("{}", msg))?;
+        let palette = VoxelPalette::from_vox(&vox.palette);
+        let graph = SceneGraph::parse(data)?;
+
+        let models: Vec<_> = vox
+            .models
+            .into_iter()
+            .map(|m| {
+                let size: Vec3i = (m.size.x as i32, m.size.y as i32, m.size.z as i32).into();
+                (VoxelModel::from_dot_vox(m, &palette), size)
+            })
+            .collect();
+
+        let mut placed = Vec::with_capacity(models.len());
+        if graph.nodes.is_empty() {
+            for (model, _) in models {
+                placed.push(VoxSceneModel {
+                    name: None,
+                    model,
+                    position: (0, 0, 0).into(),
+                    rotation: VoxRotation::default(),
+                    layer: None,
+                    hidden: false,
+                });
+            }
+        } else {
+            let mut instances = Vec::new();
+            graph.walk(0, &Placement::default(), 0, &mut instances)?;
+
+            for (model_id, placement) in instances {
+                // Shapes can be instantiated several times, hence the clone.
+                let (model, size) = models
+                    .get(model_id as usize)
+                    .ok_or_else(|| anyhow!("shape references unknown model {}", model_id))?;
+
+                // MagicaVoxel translates the center of the model, not its
+                // corner, and rotates the model around that same point.
+                let half_size: Vec3i = (size.x / 2, size.y / 2, size.z / 2).into();
+                let position = *placement.translation - *placement.rotation.apply(&half_size);
+
+                placed.push(VoxSceneModel {
+                    hidden: placement.hidden || graph.is_layer_hidden(placement.layer),
+                    name: placement.name,
+                    model: model.clone(),
+                    position: position.into(),
+                    rotation: placement.rotation,
+                    layer: placement.layer,
+                });
+            }
+        }
+
+        Ok(VoxScene {
+            models: placed,
+            layers: graph.layers,
+        })
+    }
+
+    /// Returns a copy of the model with `rotation` baked into its voxels.
+    pub fn rotated(&self, rotation: &VoxRotation) -> VoxelModel {
+        let mut model = VoxelModel {
+            chunks: HashMap::new(),
+            palette: self.palette.clone(),
+        };
+        for (pos, v) in self.iter().filter(|&(_, v)| v != 0) {
+            model.set(&rotation.apply_voxel(&pos), v);
+        }
+        model.remesh();
+
+        model
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+struct Placement {
+    name: Option<String>,
+    translation: Vec3i,
+    rotation: VoxRotation,
+    layer: Option<i32>,
+    hidden: bool,
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Self {
+            name: None,
+            translation: (0, 0, 0).into(),
+            rotation: VoxRotation::default(),
+            layer: None,
+            hidden: false,
+        }
+    }
+}
+
+type Dict = HashMap<String, String>;
+
+#[derive(Debug)]
+enum SceneNode {
+    Transform {
+        attrs: Dict,
+        child: i32,
+        layer: i32,
+        frame: Dict,
+    },
+    Group {
+        children: Vec<i32>,
+    },
+    Shape {
+        models: Vec<i32>,
+    },
+}
+
+#[derive(Debug, Default)]
+struct SceneGraph {
+    nodes: HashMap<i32, SceneNode>,
+    layers: Vec<VoxLayer>,
+}
+
+impl SceneGraph {
+    /// Scene graphs are shallow in practice, anything deeper than that is
+    /// most likely a cycle.
+    const MAX_DEPTH: usize = 64;
+
+    fn parse(data: &[u8]) -> AnyResult<Self> {
+        let mut r = Reader(data);
+        if r.bytes(4)? != b"VOX " {
+            return Err(anyhow!("not a .vox file"));
+        }
+        r.i32()?;
+
+        // MAIN holds every other chunk as its children.
+        let (id, _, mut children) = r.chunk()?;
+        if id != b"MAIN" {
+            return Err(anyhow!("missing MAIN chunk"));
+        }
+
+        let mut graph = Self::default();
+        while !children.0.is_empty() {
+            let (id, mut r, _) = children.chunk()?;
+            match id {
+                b"nTRN" => {
+                    let node_id = r.i32()?;
+                    let attrs = r.dict()?;
+                    let child = r.i32()?;
+                    r.i32()?; // reserved
+                    let layer = r.i32()?;
+                    let nb_frames = r.i32()?;
+                    let frame = if nb_frames > 0 {
+                        r.dict()?
+                    } else {
+                        Dict::new()
+                    };
+                    let node = SceneNode::Transform {
+                        attrs,
+                        child,
+                        layer,
+                        frame,
+                    };
+                    graph.nodes.insert(node_id, node);
+                }
+                b"nGRP" => {
+                    let node_id = r.i32()?;
+                    r.dict()?;
+                    let nb_children = r.i32()?;
+                    let children = (0..nb_children)
+                        .map(|_| r.i32())
+                        .collect::<AnyResult<_>>()?;
+                    graph.nodes.insert(node_id, SceneNode::Group { children });
+                }
+                b"nSHP" => {
+                    let node_id = r.i32()?;
+                    r.dict()?;
+                    let nb_models = r.i32()?;
+                    let mut models = Vec::with_capacity(nb_models.max(0) as usize);
+                    for _ in 0..nb_models {
+                        models.push(r.i32()?);
+                        r.dict()?;
+                    }
+                    graph.nodes.insert(node_id, SceneNode::Shape { models });
+                }
+                b"LAYR" => {
+                    let id = r.i32()?;
+                    let attrs = r.dict()?;
+                    graph.layers.push(VoxLayer {
+                        id,
+                        name: attrs.get("_name").cloned(),
+                        hidden: attrs.get("_hidden").map_or(false, |h| h == "1"),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        Ok(graph)
+    }
+
+    fn is_layer_hidden(&self, layer: Option<i32>) -> bool {
+        layer.map_or(false, |id| {
+            self.layers.iter().any(|l| l.id == id && l.hidden)
+        })
+    }
+
+    /// Accumulates the placement of every shape reachable from `node_id`.
+    fn walk(
+        &self,
+        node_id: i32,
+        parent: &Placement,
+        depth: usize,
+        instances: &mut Vec<(i32, Placement)>,
+    ) -> AnyResult<()> {
+        if depth > Self::MAX_DEPTH {
+            return Err(anyhow!("scene graph is too deep"));
+        }
+        let node = self
+            .nodes
+            .get(&node_id)
+            .ok_or_else(|| anyhow!("unknown scene node {}", node_id))?;
+
+        match node {
+            SceneNode::Transform {
+                attrs,
+                child,
+                layer,
+                frame,
+            } => {
+                let rotation = match frame.get("_r") {
+                    Some(r) => r
+                        .parse()
+                        .ok()
+                        .and_then(VoxRotation::from_byte)
+                        .ok_or_else(|| anyhow!("invalid rotation '{}'", r))?,
+                    None => VoxRotation::default(),
+                };
+                let translation: Vec3i = match frame.get("_t") {
+                    Some(t) => {
+                        let t = t
+                            .split_whitespace()
+                            .map(|c| c.parse::<i32>())
+                            .collect::<Result<Vec<_>, _>>()
+                            .map_err(|_| anyhow!("invalid translation '{}'", t))?;
+                        match t.as_slice() {
+                            [x, y, z] => (*x, *y, *z).into(),
+                            _ => return Err(anyhow!("invalid translation {:?}", t)),
+                        }
+                    }
+                    None => (0, 0, 0).into(),
+                };
+
+                let placement = Placement {
+                    name: attrs.get("_name").cloned().or_else(|| parent.name.clone()),
+                    translation: (*parent.translation + *parent.rotation.apply(&translation))
+                        .into(),
+                    rotation: parent.rotation.then(&rotation),
+                    layer: if *layer >= 0 {
+                        Some(*layer)
+                    } else {
+                        parent.layer
+                    },
+                    hidden: parent.hidden || attrs.get("_hidden").map_or(false, |h| h == "1"),
+                };
+                self.walk(*child, &placement, depth + 1, instances)
+            }
+            SceneNode::Group { children } => children
+                .iter()
+                .try_for_each(|&child| self.walk(child, parent, depth + 1, instances)),
+            SceneNode::Shape { models } => {
+                models
+                    .iter()
+                    .for_each(|&model_id| instances.push((model_id, parent.clone())));
+                Ok(())
+            }
+        }
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> AnyResult<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(anyhow!("unexpected end of .vox data"));
+        }
+        let (bytes, rest) = self.0.split_at(n);
+        self.0 = rest;
+
+        Ok(bytes)
+    }
+
+    fn i32(&mut self) -> AnyResult<i32> {
+        let b = self.bytes(4)?;
+        Ok(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn string(&mut self) -> AnyResult<String> {
+        let len = self.i32()?;
+        let bytes = self.bytes(len.max(0) as usize)?;
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    fn dict(&mut self) -> AnyResult<Dict> {
+        let len = self.i32()?;
+        (0..len)
+            .map(|_| Ok((self.string()?, self.string()?)))
+            .collect()
+    }
+
+    /// Returns the id of the chunk and readers over its content and its
+    /// children.
+    fn chunk(&mut self) -> AnyResult<(&'a [u8], Reader<'a>, Reader<'a>)> {
+        let id = self.bytes(4)?;
+        let content_len = self.i32()?.max(0) as usize;
+        let children_len = self.i32()?.max(0) as usize;
+        let content = Reader(self.bytes(content_len)?);
+        let children = Reader(self.bytes(children_len)?);
+
+        Ok((id, content, children))
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+fn write_chunk(out: &mut Vec<u8>, id: [u8; 4], content: &[u8], children: &[u8]) {
+    out.extend_from_slice(&id);
+    out.extend_from_slice(&(content.len() as i32).to_le_bytes());
+    out.extend_from_slice(&(children.len() as i32).to_le_bytes());
+    out.extend_from_slice(content);
+    out.extend_from_slice(children);
+}
+
+fn write_string(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(&(s.len() as i32).to_le_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn write_dict(out: &mut Vec<u8>, dict: &[(&str, &str)]) {
+    out.extend_from_slice(&(dict.len() as i32).to_le_bytes());
+    for (k, v) in dict {
+        write_string(out, k);
+        write_string(out, v);
+    }
+}
+
+fn write_i32s(out: &mut Vec<u8>, values: &[i32]) {
+    values
+        .iter()
+        .for_each(|v| out.extend_from_slice(&v.to_le_bytes()));
+}
+
+impl VoxelModel {
+    /// Serializes the model into a MagicaVoxel `.vox` file.
+    ///
+    /// The model gets split into as many `.vox` models as necessary to fit
+    /// within the 256³ limit of the format; a scene graph keeps them in place
+    /// relative to each other.
+    pub fn to_vox(&self) -> Vec<u8> {
+        let min = self.bounds().map_or((0, 0, 0).into(), |(min, _)| min);
+
+        let mut blocks: HashMap<Vec3i, Vec<[u8; 4]>> = HashMap::new();
+        for (pos, v) in self.iter().filter(|&(_, v)| v != 0) {
+            let pos = *pos - *min;
+            let block: Vec3i = (
+                pos.x / VOX_MAX_SIZE * VOX_MAX_SIZE,
+                pos.y / VOX_MAX_SIZE * VOX_MAX_SIZE,
+                pos.z / VOX_MAX_SIZE * VOX_MAX_SIZE,
+            )
+                .into();
+            let local = pos - *block;
+            blocks
+                .entry(block)
+                .or_default()
+                .push([local.x as u8, local.y as u8, local.z as u8, v]);
+        }
+
+        // Keep the output deterministic.
+        let mut blocks: Vec<_> = blocks.into_iter().collect();
+        blocks.sort_by_key(|(block, _)| (block.z, block.y, block.x));
+        // The format requires at least one model, even an empty one.
+        if blocks.is_empty() {
+            blocks.push(((0, 0, 0).into(), Vec::new()));
+        }
+
+        let mut children = Vec::new();
+        let mut sizes = Vec::with_capacity(blocks.len());
+        for (_, voxels) in &blocks {
+            let size = voxels.iter().fold([1i32; 3], |size, v| {
+                [
+                    size[0].max(v[0] as i32 + 1),
+                    size[1].max(v[1] as i32 + 1),
+                    size[2].max(v[2] as i32 + 1),
+                ]
+            });
+            let mut content = Vec::with_capacity(12);
+            write_i32s(&mut content, &size);
+            write_chunk(&mut children, *b"SIZE", &content, &[]);
+            sizes.push(size);
+
+            let mut content = Vec::with_capacity(4 + voxels.len() * 4);
+            write_i32s(&mut content, &[voxels.len() as i32]);
+            voxels.iter().for_each(|v| content.extend_from_slice(v));
+            write_chunk(&mut children, *b"XYZI", &content, &[]);
+        }
+
+        // Scene graph: root transform -> group -> one transform + shape per
+        // model.
+        let nb_models = blocks.len() as i32;
+        {
+            let mut content = Vec::new();
+            write_i32s(&mut content, &[0]);
+            write_dict(&mut content, &[]);
+            write_i32s(&mut content, &[1, -1, -1, 1]);
+            write_dict(&mut content, &[]);
+            write_chunk(&mut children, *b"nTRN", &content, &[]);
+
+            let mut content = Vec::new();
+            write_i32s(&mut content, &[1]);
+            write_dict(&mut content, &[]);
+            write_i32s(&mut content, &[nb_models]);
+            write_i32s(
+                &mut content,
+                &(0..nb_models).map(|i| 2 + i * 2).collect::<Vec<_>>(),
+            );
+            write_chunk(&mut children, *b"nGRP", &content, &[]);
+        }
+        for (i, ((block, _), size)) in blocks.iter().zip(sizes.iter()).enumerate() {
+            let (trn_id, shp_id) = (2 + i as i32 * 2, 3 + i as i32 * 2);
+
+            // MagicaVoxel translates the center of the model, not its corner.
+            let t = format!(
+                "{} {} {}",
+                min.x + block.x + size[0] / 2,
+                min.y + block.y + size[1] / 2,
+                min.z + block.z + size[2] / 2,
+            );
+            let mut content = Vec::new();
+            write_i32s(&mut content, &[trn_id]);
+            write_dict(&mut content, &[]);
+            write_i32s(&mut content, &[shp_id, -1, 0, 1]);
+            write_dict(&mut content, &[("_t", &t)]);
+            write_chunk(&mut children, *b"nTRN", &content, &[]);
+
+            let mut content = Vec::new();
+            write_i32s(&mut content, &[shp_id]);
+            write_dict(&mut content, &[]);
+            write_i32s(&mut content, &[1, i as i32]);
+            write_dict(&mut content, &[]);
+            write_chunk(&mut children, *b"nSHP", &content, &[]);
+        }
+
+        // The color of the voxel `i` lives at `i - 1` in the palette chunk.
+        let content: Vec<u8> = (1..=256)
+            .map(|i| {
+                let c = self.palette.0.get(i).copied().unwrap_or_default();
+                vec![c.r, c.g, c.b, c.a]
+            })
+            .flatten()
+            .collect();
+        write_chunk(&mut children, *b"RGBA", &content, &[]);
+
+        let mut out = Vec::with_capacity(8 + 12 + children.len());
+        out.extend_from_slice(b"VOX ");
+        out.extend_from_slice(&VOX_VERSION.to_le_bytes());
+        write_chunk(&mut out, *b"MAIN", &[], &children);
+
+        out
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    macro_rules! scan {
+        ($name:literal) => {
+            &include_bytes!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/submodules/voxel-model/vox/scan/",
+                $name,
+                ".vox"
+            ))[..]
+        };
+    }
+
+    // `.vox` coordinates are unsigned: only compare positions relative to the
+    // bounds of the model.
+    fn voxels(model: &VoxelModel) -> HashSet<(Vec3i, u8)> {
+        let min = model.bounds().map_or((0, 0, 0).into(), |(min, _)| min);
+        model
+            .iter()
+            .filter(|&(_, v)| v != 0)
+            .map(|(pos, v)| ((*pos - *min).into(), v))
+            .collect()
+    }
+
+    fn assert_same_palette(a: &VoxelPalette, b: &VoxelPalette) {
+        assert_eq!(&a.0[..], &b.0[..]);
+    }
+
+    /// Hand-rolls a `.vox` file out of `models` and raw scene graph chunks.
+    fn vox_file(models: &[([i32; 3], &[[u8; 4]])], graph: &[u8]) -> Vec<u8> {
+        let mut children = Vec::new();
+        for (size, voxels) in models {
+            let mut content = Vec::new();
+            write_i32s(&mut content, size);
+            write_chunk(&mut children, *b"SIZE", &content, &[]);
+
+            let mut content = Vec::new();
+            write_i32s(&mut content, &[voxels.len() as i32]);
+            voxels.iter().for_each(|v| content.extend_from_slice(v));
+            write_chunk(&mut children, *b"XYZI", &content, &[]);
+        }
+        children.extend_from_slice(graph);
+
+        let mut out = Vec::new();
+        out.extend_from_slice(b"VOX ");
+        out.extend_from_slice(&VOX_VERSION.to_le_bytes());
+        write_chunk(&mut out, *b"MAIN", &[], &children);
+
+        out
+    }
+
+    fn trn(
+        out: &mut Vec<u8>,
+        id: i32,
+        attrs: &[(&str, &str)],
+        child: i32,
+        layer: i32,
+        frame: &[(&str, &str)],
+    ) {
+        let mut content = Vec::new();
+        write_i32s(&mut content, &[id]);
+        write_dict(&mut content, attrs);
+        write_i32s(&mut content, &[child, -1, layer, 1]);
+        write_dict(&mut content, frame);
+        write_chunk(out, *b"nTRN", &content, &[]);
+    }
+
+    fn grp(out: &mut Vec<u8>, id: i32, children: &[i32]) {
+        let mut content = Vec::new();
+        write_i32s(&mut content, &[id]);
+        write_dict(&mut content, &[]);
+        write_i32s(&mut content, &[children.len() as i32]);
+        write_i32s(&mut content, children);
+        write_chunk(out, *b"nGRP", &content, &[]);
+    }
+
+    fn shp(out: &mut Vec<u8>, id: i32, model: i32) {
+        let mut content = Vec::new();
+        write_i32s(&mut content, &[id]);
+        write_dict(&mut content, &[]);
+        write_i32s(&mut content, &[1, model]);
+        write_dict(&mut content, &[]);
+        write_chunk(out, *b"nSHP", &content, &[]);
+    }
+
+    fn layr(out: &mut Vec<u8>, id: i32, attrs: &[(&str, &str)]) {
+        let mut content = Vec::new();
+        write_i32s(&mut content, &[id]);
+        write_dict(&mut content, attrs);
+        write_i32s(&mut content, &[-1]);
+        write_chunk(out, *b"LAYR", &content, &[]);
+    }
+
+    /// Where the non-empty voxels of `m` end up in the scene, once rotated.
+    fn placed(m: &VoxSceneModel) -> HashSet<(Vec3i, u8)> {
+        m.model
+            .rotated(&m.rotation)
+            .iter()
+            .filter(|&(_, v)| v != 0)
+            .map(|(pos, v)| ((*m.position + *pos).into(), v))
+            .collect()
+    }
+
+    #[test]
+    fn rotation_from_byte() {
+        // Rows pick their column on bits 0-1 and 2-3, the third row gets
+        // whatever column is left.
+        assert_eq!(
+            Some(VoxRotation::default()),
+            VoxRotation::from_byte(0b000_0100)
+        );
+        assert_eq!(
+            Some(VoxRotation([[0, 1, 0], [1, 0, 0], [0, 0, 1]])),
+            VoxRotation::from_byte(0b000_0001)
+        );
+        assert_eq!(
+            Some(VoxRotation([[0, 0, 1], [1, 0, 0], [0, 1, 0]])),
+            VoxRotation::from_byte(0b000_0010)
+        );
+
+        // Bits 4 through 6 flip rows 0 through 2.
+        assert_eq!(
+            Some(VoxRotation([[-1, 0, 0], [0, 1, 0], [0, 0, 1]])),
+            VoxRotation::from_byte(0b001_0100)
+        );
+        assert_eq!(
+            Some(VoxRotation([[1, 0, 0], [0, -1, 0], [0, 0, 1]])),
+            VoxRotation::from_byte(0b010_0100)
+        );
+        assert_eq!(
+            Some(VoxRotation([[0, 1, 0], [-1, 0, 0], [0, 0, -1]])),
+            VoxRotation::from_byte(0b110_0001)
+        );
+
+        // Rows sharing a column, or pointing past the third one.
+        assert_eq!(None, VoxRotation::from_byte(0b000_0000));
+        assert_eq!(None, VoxRotation::from_byte(0b000_0111));
+        assert_eq!(None, VoxRotation::from_byte(0b000_1011));
+    }
+
+    #[test]
+    fn rotation_apply_voxel() {
+        let flip_x = VoxRotation::from_byte(0b001_0100).unwrap();
+        assert_eq!(
+            Vec3i::from((-1, 0, 0)),
+            flip_x.apply_voxel(&(0, 0, 0).into())
+        );
+        assert_eq!(
+            Vec3i::from((-4, 2, 3)),
+            flip_x.apply_voxel(&(3, 2, 3).into())
+        );
+
+        // x -> y, y -> -x: a quarter turn around Z.
+        let quarter = VoxRotation([[0, -1, 0], [1, 0, 0], [0, 0, 1]]);
+        assert_eq!(
+            Vec3i::from((-3, 1, 0)),
+            quarter.apply_voxel(&(1, 2, 0).into())
+        );
+    }
+
+    #[test]
+    fn scene_nested_transforms() {
+        let one: &[[u8; 4]] = &[[0, 0, 0, 1]];
+        let mut graph = Vec::new();
+        // Swaps X & Y, then moves along X.
+        trn(
+            &mut graph,
+            0,
+            &[("_name", "unit")],
+            1,
+            -1,
+            &[("_r", "1"), ("_t", "10 0 0")],
+        );
+        grp(&mut graph, 1, &[2, 4]);
+        // In the frame of the root: Y becomes X.
+        trn(&mut graph, 2, &[], 3, -1, &[("_t", "0 5 0")]);
+        shp(&mut graph, 3, 0);
+        trn(
+            &mut graph,
+            4,
+            &[("_name", "other")],
+            5,
+            -1,
+            &[("_t", "0 0 3")],
+        );
+        shp(&mut graph, 5, 0);
+
+        let scene = VoxelModel::from_vox_scene(&vox_file(&[([1, 1, 1], one)], &graph)).unwrap();
+        assert_eq!(2, scene.models.len());
+
+        let swap = VoxRotation([[0, 1, 0], [1, 0, 0], [0, 0, 1]]);
+        let (a, b) = (&scene.models[0], &scene.models[1]);
+        assert_eq!(Some("unit"), a.name.as_deref());
+        assert_eq!(Vec3i::from((15, 0, 0)), a.position);
+        assert_eq!(swap, a.rotation);
+        assert_eq!(Some("other"), b.name.as_deref());
+        assert_eq!(Vec3i::from((10, 0, 3)), b.position);
+        assert_eq!(swap, b.rotation);
+    }
+
+    #[test]
+    fn scene_hidden_layers() {
+        let one: &[[u8; 4]] = &[[0, 0, 0, 1]];
+        let mut graph = Vec::new();
+        trn(&mut graph, 0, &[], 1, -1, &[]);
+        grp(&mut graph, 1, &[2, 4, 6, 8]);
+        // On a hidden layer.
+        trn(&mut graph, 2, &[], 3, 0, &[]);
+        shp(&mut graph, 3, 0);
+        // Hidden itself.
+        trn(&mut graph, 4, &[("_hidden", "1")], 5, 1, &[]);
+        shp(&mut graph, 5, 0);
+        // Visible, on a visible layer.
+        trn(&mut graph, 6, &[], 7, 1, &[]);
+        shp(&mut graph, 7, 0);
+        // Hidden through its parent.
+        trn(&mut graph, 8, &[("_hidden", "1")], 9, -1, &[]);
+        trn(&mut graph, 9, &[], 10, 1, &[]);
+        shp(&mut graph, 10, 0);
+        layr(&mut graph, 0, &[("_name", "hidden"), ("_hidden", "1")]);
+        layr(&mut graph, 1, &[("_name", "visible")]);
+
+        let scene = VoxelModel::from_vox_scene(&vox_file(&[([1, 1, 1], one)], &graph)).unwrap();
+        let hidden: Vec<_> = scene.models.iter().map(|m| (m.layer, m.hidden)).collect();
+        assert_eq!(
+            vec![
+                (Some(0), true),
+                (Some(1), true),
+                (Some(1), false),
+                (Some(1), true)
+            ],
+            hidden
+        );
+
+        assert_eq!(2, scene.layers.len());
+        assert_eq!(Some("hidden"), scene.layers[0].name.as_deref());
+        assert!(scene.layers[0].hidden);
+        assert!(!scene.layers[1].hidden);
+    }
+
+    #[test]
+    fn scene_flipped_even_model() {
+        // 4 voxels wide, each of its own color so that the flip shows.
+        let row: &[[u8; 4]] = &[[0, 0, 0, 1], [1, 0, 0, 2], [2, 0, 0, 3], [3, 0, 0, 4]];
+        let scene = |r: &str| {
+            let mut graph = Vec::new();
+            trn(&mut graph, 0, &[], 1, -1, &[("_r", r), ("_t", "10 0 0")]);
+            shp(&mut graph, 1, 0);
+            let data = vox_file(&[([4, 1, 1], row)], &graph);
+            VoxelModel::from_vox_scene(&data).unwrap().models.remove(0)
+        };
+
+        // Centered on `_t` either way, i.e. covering [8, 12)...
+        let model = scene("4");
+        let expected: HashSet<_> = (0..4)
+            .map(|x| ((8 + x, 0, 0).into(), x as u8 + 1))
+            .collect();
+        assert_eq!(expected, placed(&model));
+
+        // ...only mirrored.
+        let model = scene("20");
+        let expected: HashSet<_> = (0..4)
+            .map(|x| ((11 - x, 0, 0).into(), x as u8 + 1))
+            .collect();
+        assert_eq!(expected, placed(&model));
+    }
+
+    #[test]
+    fn to_vox_round_trip_scans() {
+        for data in &[scan!("dragon"), scan!("teapot")] {
+            for model in VoxelModel::from_vox(data).unwrap() {
+                let models = VoxelModel::from_vox(&model.to_vox()).unwrap();
+                assert_eq!(1, models.len());
+                assert_eq!(voxels(&model), voxels(&models[0]));
+                assert_same_palette(model.palette(), models[0].palette());
+            }
+        }
+    }
+
+    #[test]
+    fn to_vox_round_trip_split() {
+        // Odd sizes on every axis, and negative coordinates, to exercise the
+        // centering of `_t`.
+        let mut model = VoxelModel::default();
+        for x in -20..291 {
+            model.set(&(x, 0, -7).into(), (x.rem_euclid(255) + 1) as u8);
+        }
+        model.set(&(-20, 8, 4).into(), 1);
+        model.set(&(290, 8, 4).into(), 2);
+        model.remesh();
+
+        let scene = VoxelModel::from_vox_scene(&model.to_vox()).unwrap();
+        assert_eq!(2, scene.models.len());
+
+        let mut placed = HashSet::new();
+        for m in &scene.models {
+            assert_eq!(VoxRotation::default(), m.rotation);
+            assert_same_palette(model.palette(), m.model.palette());
+            for (pos, v) in m.model.iter().filter(|&(_, v)| v != 0) {
+                let pos = *m.position + *m.rotation.apply_voxel(&pos);
+                assert!(
+                    placed.insert((Vec3i::from(pos), v)),
+                    "{:?} placed twice",
+                    pos
+                );
+            }
+        }
+
+        let expected: HashSet<_> = model.iter().filter(|&(_, v)| v != 0).collect();
+        assert_eq!(expected, placed);
+    }
+}